@@ -0,0 +1,92 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use serde_json::{json, Value};
+use tailscale_localapi::Status;
+
+/// Peer as tailscaled reports it in `/localapi/v0/status`
+fn peer(index: usize) -> Value {
+    let (high, low) = (index / 256, index % 256);
+    json!({
+        "ID": format!("n{index:012}CNTRL"),
+        "PublicKey": format!("nodekey:{index:064x}"),
+        "HostName": format!("host-{index}"),
+        "DNSName": format!("host-{index}.example.ts.net."),
+        "OS": "linux",
+        "UserID": 1000 + index % 50,
+        "TailscaleIPs": [format!("100.64.{high}.{low}"), format!("fd7a:115c:a1e0::{index:x}")],
+        "AllowedIPs": [format!("100.64.{high}.{low}/32"), format!("fd7a:115c:a1e0::{index:x}/128")],
+        "Tags": ["tag:server"],
+        "PrimaryRoutes": null,
+        "Addrs": [format!("203.0.113.{low}:41641"), format!("192.168.1.{low}:41641")],
+        "CurAddr": format!("203.0.113.{low}:41641"),
+        "Relay": "nyc",
+        "RxBytes": 123_456 * index,
+        "TxBytes": 654_321 * index,
+        "Created": "2024-01-02T03:04:05.123456789Z",
+        "KeyExpiry": "2025-01-02T03:04:05Z",
+        "LastWrite": "2024-06-01T12:00:00.5Z",
+        "LastSeen": "2024-06-01T12:00:00Z",
+        "LastHandshake": "2024-06-01T11:59:30.25Z",
+        "Online": !index.is_multiple_of(3),
+        "KeepAlive": false,
+        "ExitNode": false,
+        "ExitNodeOption": index.is_multiple_of(10),
+        "Active": index.is_multiple_of(2),
+        "PeerAPIURL": [format!("http://100.64.{high}.{low}:45678")],
+        "Capabilities": ["https://tailscale.com/cap/file-sharing", "https://tailscale.com/cap/ssh"],
+        "sshHostKeys": [format!("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI{index:040x}")],
+        "InNetworkMap": true,
+        "InMagicSock": true,
+        "InEngine": true,
+    })
+}
+
+/// Status response for a tailnet with the given number of peers
+fn status(peers: usize) -> Vec<u8> {
+    let users: serde_json::Map<String, Value> = (0..50)
+        .map(|id| {
+            let user = json!({
+                "ID": 1000 + id,
+                "LoginName": format!("user{id}@example.com"),
+                "DisplayName": format!("User {id}"),
+                "ProfilePicURL": format!("https://example.com/{id}.png"),
+            });
+            ((1000 + id).to_string(), user)
+        })
+        .collect();
+    let status = json!({
+        "Version": "1.66.4-t1234567-gabcdef",
+        "TUN": true,
+        "BackendState": "Running",
+        "AuthURL": "",
+        "TailscaleIPs": ["100.100.0.1", "fd7a:115c:a1e0::1"],
+        "Self": peer(peers),
+        "Health": [],
+        "CurrentTailnet": {
+            "Name": "example.com",
+            "MagicDNSSuffix": "example.ts.net",
+            "MagicDNSEnabled": true,
+        },
+        "CertDomains": ["host.example.ts.net"],
+        "Peer": (0..peers)
+            .map(|index| (format!("nodekey:{index:064x}"), peer(index)))
+            .collect::<serde_json::Map<_, _>>(),
+        "User": users,
+    });
+
+    serde_json::to_vec(&status).unwrap()
+}
+
+fn parse_status(c: &mut Criterion) {
+    let mut group = c.benchmark_group("status");
+    for peers in [100, 5_000] {
+        let body = status(peers);
+        group.throughput(Throughput::Bytes(body.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(peers), &body, |b, body| {
+            b.iter(|| serde_json::from_slice::<Status>(body).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse_status);
+criterion_main!(benches);
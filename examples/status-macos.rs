@@ -6,7 +6,7 @@ fn port_and_password() -> (u16, String) {
     let output = Command::new("lsof")
         .arg("-n")
         .arg("-a")
-        .arg(format!("-u{}", unsafe { libc::getuid() }))
+        .arg(format!("-u{}", rustix::process::getuid().as_raw()))
         .arg("-c")
         .arg("IPNExtension")
         .arg("-F")
@@ -0,0 +1,14 @@
+async fn run() {
+    let client = tailscale_localapi::LocalApi::new_from_environment();
+
+    dbg!(client.rediscover().await.unwrap());
+    dbg!(client.status().await.unwrap());
+}
+
+fn main() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(run());
+}
@@ -0,0 +1,438 @@
+use std::{
+    fmt,
+    ops::{BitOr, BitOrAssign},
+    time::Duration,
+};
+
+use hyper::{body::HttpBody, Body};
+
+use crate::{
+    parse_json, stream::idle, BackendState, EngineStatus, HealthState, InvalidUtf8, Notify,
+    OutgoingFile, Result,
+};
+
+/// Options controlling which notifications the IPN bus sends
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NotifyWatchOpt(pub u64);
+
+impl NotifyWatchOpt {
+    /// Send periodic engine status updates
+    pub const ENGINE_UPDATES: Self = Self(1 << 0);
+    /// Send the current backend state first
+    pub const INITIAL_STATE: Self = Self(1 << 1);
+    /// Send the current prefs first
+    pub const INITIAL_PREFS: Self = Self(1 << 2);
+    /// Send the current netmap first
+    pub const INITIAL_NETMAP: Self = Self(1 << 3);
+    /// Strip private keys from prefs and netmaps
+    pub const NO_PRIVATE_KEYS: Self = Self(1 << 4);
+    /// Send the current drive shares first
+    pub const INITIAL_DRIVE_SHARES: Self = Self(1 << 5);
+    /// Send the current outgoing Taildrop files first
+    pub const INITIAL_OUTGOING_FILES: Self = Self(1 << 6);
+    /// Send the current health state first
+    pub const INITIAL_HEALTH_STATE: Self = Self(1 << 7);
+    /// Rate limit netmap updates
+    pub const RATE_LIMIT: Self = Self(1 << 8);
+
+    /// Whether all options in `other` are set
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for NotifyWatchOpt {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for NotifyWatchOpt {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Stream of notifications from the IPN bus, created with
+/// [`crate::LocalApi::watch_ipn_bus`]. The connection stays open until the
+/// watcher is dropped.
+pub struct IpnBusWatcher {
+    body: Body,
+    buffer: Vec<u8>,
+    invalid_utf8: InvalidUtf8,
+    idle_timeout: Option<Duration>,
+}
+
+impl IpnBusWatcher {
+    pub(crate) fn new(
+        body: Body,
+        invalid_utf8: InvalidUtf8,
+        idle_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            body,
+            buffer: Vec::new(),
+            invalid_utf8,
+            idle_timeout,
+        }
+    }
+
+    /// Wait for the next notification. Returns `None` once tailscaled closes
+    /// the bus, or [`crate::Error::Timeout`] if nothing arrives within the
+    /// idle timeout.
+    pub async fn next(&mut self) -> Result<Option<Notify>> {
+        loop {
+            if let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+
+                return Ok(Some(parse_json(&line, self.invalid_utf8)?));
+            }
+
+            match idle(self.idle_timeout, self.body.data()).await? {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk?),
+                None if self.buffer.iter().all(u8::is_ascii_whitespace) => return Ok(None),
+                None => {
+                    let line = std::mem::take(&mut self.buffer);
+                    return Ok(Some(parse_json(&line, self.invalid_utf8)?));
+                }
+            }
+        }
+    }
+}
+
+const STATE: u8 = 1 << 0;
+const PREFS: u8 = 1 << 1;
+const NETMAP: u8 = 1 << 2;
+const HEALTH: u8 = 1 << 3;
+const ENGINE: u8 = 1 << 4;
+const OUTGOING_FILES: u8 = 1 << 5;
+
+/// Options set by selecting fields of a [`NotifyFilter`]
+const SELECTION_OPTIONS: NotifyWatchOpt = NotifyWatchOpt(
+    NotifyWatchOpt::INITIAL_STATE.0
+        | NotifyWatchOpt::INITIAL_PREFS.0
+        | NotifyWatchOpt::INITIAL_NETMAP.0
+        | NotifyWatchOpt::INITIAL_HEALTH_STATE.0
+        | NotifyWatchOpt::ENGINE_UPDATES.0
+        | NotifyWatchOpt::INITIAL_OUTGOING_FILES.0,
+);
+
+/// Selection of IPN bus notifications, setting the matching
+/// [`NotifyWatchOpt`] bits and dropping everything else as it arrives. For
+/// example `NotifyFilter::new().states().health()` yields notifications with
+/// a state or health change, and `NotifyFilter::new().health_only()` yields
+/// just the [`HealthState`] of each. The `*_only` methods replace any fields
+/// selected before them.
+pub struct NotifyFilter<T = Notify> {
+    options: NotifyWatchOpt,
+    fields: u8,
+    extract: fn(Notify) -> Result<Option<T>>,
+}
+
+impl NotifyFilter {
+    /// Create a filter with nothing selected, which passes every
+    /// notification through.
+    pub fn new() -> Self {
+        Self {
+            options: NotifyWatchOpt::default(),
+            fields: 0,
+            extract: |notify| Ok(Some(notify)),
+        }
+    }
+
+    /// Backend state changes, starting with the current state
+    pub fn states(self) -> Self {
+        self.select(NotifyWatchOpt::INITIAL_STATE, STATE)
+    }
+
+    /// Prefs changes, starting with the current prefs
+    pub fn prefs(self) -> Self {
+        self.select(NotifyWatchOpt::INITIAL_PREFS, PREFS)
+    }
+
+    /// Netmap changes, starting with the current netmap
+    pub fn netmap(self) -> Self {
+        self.select(NotifyWatchOpt::INITIAL_NETMAP, NETMAP)
+    }
+
+    /// Health changes, starting with the current health
+    pub fn health(self) -> Self {
+        self.select(NotifyWatchOpt::INITIAL_HEALTH_STATE, HEALTH)
+    }
+
+    /// Periodic engine status updates
+    pub fn engine(self) -> Self {
+        self.select(NotifyWatchOpt::ENGINE_UPDATES, ENGINE)
+    }
+
+    /// Progress of Taildrop files being sent, starting with the current
+    /// transfers
+    pub fn outgoing_files(self) -> Self {
+        self.select(NotifyWatchOpt::INITIAL_OUTGOING_FILES, OUTGOING_FILES)
+    }
+
+    /// Only backend states, starting with the current state
+    pub fn states_only(self) -> NotifyFilter<BackendState> {
+        self.only(NotifyWatchOpt::INITIAL_STATE, STATE, |notify| {
+            Ok(notify.state)
+        })
+    }
+
+    /// Only prefs, starting with the current prefs
+    pub fn prefs_only(self) -> NotifyFilter<serde_json::Value> {
+        self.only(NotifyWatchOpt::INITIAL_PREFS, PREFS, |notify| {
+            Ok(notify.prefs)
+        })
+    }
+
+    /// Only netmaps, starting with the current netmap
+    pub fn netmap_only(self) -> NotifyFilter<serde_json::Value> {
+        self.only(NotifyWatchOpt::INITIAL_NETMAP, NETMAP, |notify| {
+            Ok(notify.net_map)
+        })
+    }
+
+    /// Only health states, starting with the current health
+    pub fn health_only(self) -> NotifyFilter<HealthState> {
+        self.only(NotifyWatchOpt::INITIAL_HEALTH_STATE, HEALTH, |notify| {
+            Ok(notify.health.map(serde_json::from_value).transpose()?)
+        })
+    }
+
+    /// Only the Taildrop files being sent, starting with the current
+    /// transfers
+    pub fn outgoing_files_only(self) -> NotifyFilter<Vec<OutgoingFile>> {
+        self.only(
+            NotifyWatchOpt::INITIAL_OUTGOING_FILES,
+            OUTGOING_FILES,
+            |notify| Ok(notify.outgoing_files),
+        )
+    }
+
+    /// Only engine status updates
+    pub fn engine_only(self) -> NotifyFilter<EngineStatus> {
+        self.only(NotifyWatchOpt::ENGINE_UPDATES, ENGINE, |notify| {
+            Ok(notify.engine)
+        })
+    }
+
+    /// Whether a notification has any of the selected fields. Every
+    /// notification matches when nothing is selected.
+    pub fn matches(&self, notify: &Notify) -> bool {
+        matches(self.fields, notify)
+    }
+
+    fn select(mut self, options: NotifyWatchOpt, field: u8) -> Self {
+        self.options |= options;
+        self.fields |= field;
+        self
+    }
+
+    /// Narrow the filter to a single field. Fields selected earlier cannot
+    /// be yielded as `T`, so their options are dropped as well, keeping the
+    /// others such as [`NotifyWatchOpt::NO_PRIVATE_KEYS`].
+    fn only<T>(
+        self,
+        options: NotifyWatchOpt,
+        field: u8,
+        extract: fn(Notify) -> Result<Option<T>>,
+    ) -> NotifyFilter<T> {
+        NotifyFilter {
+            options: NotifyWatchOpt(self.options.0 & !SELECTION_OPTIONS.0) | options,
+            fields: field,
+            extract,
+        }
+    }
+}
+
+impl<T> NotifyFilter<T> {
+    /// Also set the given options, such as [`NotifyWatchOpt::NO_PRIVATE_KEYS`]
+    /// or [`NotifyWatchOpt::RATE_LIMIT`]
+    pub fn with_options(mut self, options: NotifyWatchOpt) -> Self {
+        self.options |= options;
+        self
+    }
+
+    /// Options to send to tailscaled for this filter
+    pub fn options(&self) -> NotifyWatchOpt {
+        self.options
+    }
+
+    fn apply(&self, notify: Notify) -> Result<Option<T>> {
+        if matches(self.fields, &notify) {
+            (self.extract)(notify)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Default for NotifyFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for NotifyFilter<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NotifyFilter<T> {}
+
+impl<T> fmt::Debug for NotifyFilter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NotifyFilter")
+            .field("options", &self.options)
+            .field("fields", &self.fields)
+            .finish_non_exhaustive()
+    }
+}
+
+fn matches(fields: u8, notify: &Notify) -> bool {
+    fields == 0
+        || (fields & STATE != 0 && notify.state.is_some())
+        || (fields & PREFS != 0 && notify.prefs.is_some())
+        || (fields & NETMAP != 0 && notify.net_map.is_some())
+        || (fields & HEALTH != 0 && notify.health.is_some())
+        || (fields & ENGINE != 0 && notify.engine.is_some())
+        || (fields & OUTGOING_FILES != 0 && notify.outgoing_files.is_some())
+}
+
+/// [`IpnBusWatcher`] yielding only what a [`NotifyFilter`] selects, created
+/// with [`crate::LocalApi::watch_ipn_bus_filtered`]
+pub struct FilteredIpnBusWatcher<T = Notify> {
+    watcher: IpnBusWatcher,
+    filter: NotifyFilter<T>,
+}
+
+impl<T> FilteredIpnBusWatcher<T> {
+    pub(crate) fn new(watcher: IpnBusWatcher, filter: NotifyFilter<T>) -> Self {
+        Self { watcher, filter }
+    }
+
+    /// Wait for the next selected notification. Returns `None` once
+    /// tailscaled closes the bus.
+    pub async fn next(&mut self) -> Result<Option<T>> {
+        while let Some(notify) = self.watcher.next().await? {
+            if let Some(item) = self.filter.apply(notify)? {
+                return Ok(Some(item));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notify(json: serde_json::Value) -> Notify {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn watch<T>(lines: &'static str, filter: NotifyFilter<T>) -> FilteredIpnBusWatcher<T> {
+        let watcher = IpnBusWatcher::new(Body::from(lines), InvalidUtf8::Reject, None);
+        FilteredIpnBusWatcher::new(watcher, filter)
+    }
+
+    #[test]
+    fn filters_set_the_options_of_their_fields() {
+        let filter = NotifyFilter::new()
+            .states()
+            .health()
+            .with_options(NotifyWatchOpt::NO_PRIVATE_KEYS);
+        assert_eq!(
+            filter.options(),
+            NotifyWatchOpt::INITIAL_STATE
+                | NotifyWatchOpt::INITIAL_HEALTH_STATE
+                | NotifyWatchOpt::NO_PRIVATE_KEYS
+        );
+        assert_eq!(NotifyFilter::new().options(), NotifyWatchOpt::default());
+        assert_eq!(
+            NotifyFilter::new().engine_only().options(),
+            NotifyWatchOpt::ENGINE_UPDATES
+        );
+    }
+
+    #[test]
+    fn filters_match_any_selected_field() {
+        let state = notify(serde_json::json!({"State": 6}));
+        let health = notify(serde_json::json!({"Health": {}}));
+        let prefs = notify(serde_json::json!({"Prefs": {}}));
+
+        let filter = NotifyFilter::new().states().health();
+        assert!(filter.matches(&state));
+        assert!(filter.matches(&health));
+        assert!(!filter.matches(&prefs));
+
+        let everything = NotifyFilter::new();
+        assert!([state, health, prefs].iter().all(|n| everything.matches(n)));
+    }
+
+    #[test]
+    fn only_replaces_earlier_selections() {
+        let filter = NotifyFilter::new()
+            .states()
+            .with_options(NotifyWatchOpt::RATE_LIMIT)
+            .health_only();
+
+        assert_eq!(
+            filter.options(),
+            NotifyWatchOpt::INITIAL_HEALTH_STATE | NotifyWatchOpt::RATE_LIMIT
+        );
+        assert_eq!(filter.fields, HEALTH);
+        assert!(filter
+            .apply(notify(serde_json::json!({"State": 6})))
+            .unwrap()
+            .is_none());
+        assert!(filter
+            .apply(notify(serde_json::json!({"Health": {"Warnings": null}})))
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn filtered_watchers_skip_unselected_notifications() {
+        let mut watcher = watch(
+            "{\"Version\":\"1.80.0\"}\n{\"State\":2}\n\n{\"Prefs\":{}}\n{\"State\":6}",
+            NotifyFilter::new().states_only(),
+        );
+
+        assert_eq!(
+            watcher.next().await.unwrap(),
+            Some(BackendState::NeedsLogin)
+        );
+        assert_eq!(watcher.next().await.unwrap(), Some(BackendState::Running));
+        assert_eq!(watcher.next().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn filtered_watchers_yield_whole_notifications() {
+        let mut watcher = watch(
+            "{\"Prefs\":{}}\n{\"State\":4,\"Health\":{}}\n{\"Health\":{}}\n",
+            NotifyFilter::new().states(),
+        );
+
+        let notify = watcher.next().await.unwrap().unwrap();
+        assert_eq!(notify.state, Some(BackendState::Stopped));
+        assert!(notify.health.is_some());
+        assert!(watcher.next().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn filtered_watchers_report_bad_notifications() {
+        let mut watcher = watch(
+            "{\"Health\":{\"Warnings\":3}}\n",
+            NotifyFilter::new().health_only(),
+        );
+        assert!(watcher.next().await.is_err());
+    }
+}
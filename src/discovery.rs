@@ -0,0 +1,283 @@
+use std::{
+    env, fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use http::{Request, Response};
+use hyper::Body;
+use tokio::sync::Mutex;
+
+use crate::{Error, LocalApi, LocalApiClient, Result, TcpWithPasswordClient, UnixStreamClient};
+
+/// Known paths of the tailscaled socket on Linux and other Unix-like systems,
+/// in the order they are tried. Besides the standard locations, which are
+/// also used by FreeBSD, pfSense, and OpenWrt, this covers the Synology
+/// packages for DSM 7 and 6, QNAP, and gokrazy.
+pub const DEFAULT_SOCKET_PATHS: &[&str] = &[
+    "/var/run/tailscale/tailscaled.sock",
+    "/run/tailscale/tailscaled.sock",
+    "/var/packages/Tailscale/var/tailscaled.sock",
+    "/var/packages/Tailscale/etc/tailscaled.sock",
+    "/tmp/tailscale/tailscaled.sock",
+    "/perm/tailscaled/tailscaled.sock",
+];
+
+/// Known paths of the tailscaled socket on macOS. The apps use a TCP port
+/// instead, so this is the socket of the open source tailscaled, tried after
+/// the apps.
+pub const MACOS_SOCKET_PATHS: &[&str] = &["/var/run/tailscaled.socket"];
+
+/// How to reach the local tailscaled
+#[derive(Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Unix socket at the given path
+    UnixSocket(PathBuf),
+    /// Localhost TCP port protected by a password, used by the macOS apps
+    TcpWithPassword { port: u16, password: String },
+}
+
+impl fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnixSocket(path) => f.debug_tuple("UnixSocket").field(path).finish(),
+            Self::TcpWithPassword { port, .. } => f
+                .debug_struct("TcpWithPassword")
+                .field("port", port)
+                .field("password", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+/// Find the local tailscaled. The `TS_SOCKET` environment variable is used
+/// first, followed by the macOS apps, and finally the default socket paths,
+/// or [`MACOS_SOCKET_PATHS`] on macOS. Finding the macOS apps needs the
+/// default `macos-discovery` feature. When no socket exists the first of
+/// those paths is returned so connection errors point at it.
+pub fn discover() -> Option<Endpoint> {
+    let socket_paths = if cfg!(target_os = "macos") {
+        MACOS_SOCKET_PATHS
+    } else {
+        DEFAULT_SOCKET_PATHS
+    };
+    discover_with_socket_paths(socket_paths)
+}
+
+/// Find the local tailscaled like [`discover`], but search the given socket
+/// paths instead of [`DEFAULT_SOCKET_PATHS`]. Extend the defaults to support
+/// other layouts.
+pub fn discover_with_socket_paths<P: AsRef<Path>>(socket_paths: &[P]) -> Option<Endpoint> {
+    if let Some(path) = env::var_os("TS_SOCKET").filter(|path| !path.is_empty()) {
+        return Some(Endpoint::UnixSocket(path.into()));
+    }
+
+    #[cfg(all(target_os = "macos", feature = "macos-discovery"))]
+    if let Some(endpoint) = macos::discover() {
+        return Some(endpoint);
+    }
+
+    let path = socket_paths
+        .iter()
+        .map(AsRef::as_ref)
+        .find(|path| path.exists())
+        .or_else(|| socket_paths.first().map(AsRef::as_ref))?;
+    Some(Endpoint::UnixSocket(path.to_path_buf()))
+}
+
+#[cfg(all(target_os = "macos", feature = "macos-discovery"))]
+mod macos {
+    use std::{fs, path::Path, process::Command, str};
+
+    use super::Endpoint;
+
+    const STANDALONE_DIR: &str = "/Library/Tailscale";
+    const SAMEUSERPROOF: &[u8] = b".tailscale.ipn.macos/sameuserproof-";
+
+    pub(super) fn discover() -> Option<Endpoint> {
+        standalone().or_else(app_store)
+    }
+
+    /// The standalone app publishes its port as a symlink and its password in
+    /// a file next to it.
+    fn standalone() -> Option<Endpoint> {
+        let dir = Path::new(STANDALONE_DIR);
+        let port = fs::read_link(dir.join("ipnport"))
+            .ok()?
+            .to_string_lossy()
+            .parse()
+            .ok()?;
+        let password = fs::read_to_string(dir.join(format!("sameuserproof-{port}")))
+            .ok()?
+            .trim_end()
+            .to_string();
+
+        Some(Endpoint::TcpWithPassword { port, password })
+    }
+
+    /// The App Store app keeps the port and password in the name of a file it
+    /// holds open, which is found with lsof.
+    fn app_store() -> Option<Endpoint> {
+        let uid = rustix::process::getuid().as_raw();
+        let output = Command::new("lsof")
+            .arg("-n")
+            .arg("-a")
+            .arg(format!("-u{uid}"))
+            .arg("-c")
+            .arg("IPNExtension")
+            .arg("-F")
+            .output()
+            .ok()?;
+
+        let offset = output
+            .stdout
+            .windows(SAMEUSERPROOF.len())
+            .position(|w| w == SAMEUSERPROOF)?;
+        let start = offset + SAMEUSERPROOF.len();
+        let end = output.stdout[start..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map(|pos| start + pos)
+            .unwrap_or(output.stdout.len());
+        let port_and_password = str::from_utf8(&output.stdout[start..end]).ok()?;
+        let (port, password) = port_and_password.split_once('-')?;
+
+        Some(Endpoint::TcpWithPassword {
+            port: port.parse().ok()?,
+            password: password.to_string(),
+        })
+    }
+}
+
+/// Client that finds the local tailscaled on first use and caches the
+/// result. Call [`LocalApi::rediscover`] when the daemon may have moved, for
+/// example after the machine wakes from sleep. Clones share the cache.
+#[derive(Clone, Default)]
+pub struct DiscoveredClient {
+    cached: Arc<Mutex<Option<(Endpoint, EndpointClient)>>>,
+    pinned: bool,
+    socket_paths: Option<Arc<[PathBuf]>>,
+}
+
+#[derive(Clone)]
+enum EndpointClient {
+    Unix(UnixStreamClient),
+    Tcp(TcpWithPasswordClient),
+}
+
+impl fmt::Debug for DiscoveredClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("DiscoveredClient");
+        // Never block here since Debug may be called while a request holds
+        // the lock.
+        match self.cached.try_lock() {
+            Ok(cached) => debug.field("endpoint", &cached.as_ref().map(|(endpoint, _)| endpoint)),
+            Err(_) => debug.field("endpoint", &"<in use>"),
+        };
+        debug.field("pinned", &self.pinned).finish()
+    }
+}
+
+impl DiscoveredClient {
+    async fn client(&self) -> Result<EndpointClient> {
+        let mut cached = self.cached.lock().await;
+        if let Some((_, client)) = &*cached {
+            return Ok(client.clone());
+        }
+
+        let endpoint = self.discover().await?;
+        let client = endpoint_client(&endpoint);
+        *cached = Some((endpoint, client.clone()));
+
+        Ok(client)
+    }
+
+    async fn rediscover(&self) -> Result<Endpoint> {
+        let mut cached = self.cached.lock().await;
+        if self.pinned {
+            if let Some((endpoint, _)) = &*cached {
+                return Ok(endpoint.clone());
+            }
+        }
+
+        let endpoint = self.discover().await?;
+        *cached = Some((endpoint.clone(), endpoint_client(&endpoint)));
+
+        Ok(endpoint)
+    }
+
+    /// Run discovery off the async runtime since it reads files and may spawn
+    /// lsof.
+    async fn discover(&self) -> Result<Endpoint> {
+        let socket_paths = self.socket_paths.clone();
+        tokio::task::spawn_blocking(move || match socket_paths {
+            Some(socket_paths) => discover_with_socket_paths(&socket_paths),
+            None => discover(),
+        })
+        .await
+        .map_err(|e| Error::IoError(e.into()))?
+        .ok_or(Error::DaemonNotFound)
+    }
+}
+
+fn endpoint_client(endpoint: &Endpoint) -> EndpointClient {
+    match endpoint {
+        Endpoint::UnixSocket(path) => {
+            EndpointClient::Unix(LocalApi::new_with_socket_path(path).client)
+        }
+        Endpoint::TcpWithPassword { port, password } => EndpointClient::Tcp(
+            LocalApi::new_with_port_and_password(*port, password.clone()).client,
+        ),
+    }
+}
+
+#[async_trait]
+impl LocalApiClient for DiscoveredClient {
+    async fn request(&self, request: Request<Body>) -> Result<Response<Body>> {
+        match self.client().await? {
+            EndpointClient::Unix(client) => client.request(request).await,
+            EndpointClient::Tcp(client) => client.request(request).await,
+        }
+    }
+}
+
+impl LocalApi<DiscoveredClient> {
+    /// Create a new client that finds the local tailscaled automatically.
+    /// Discovery happens on the first request and the result is cached.
+    pub fn new_from_environment() -> Self {
+        Self::new_with_client(DiscoveredClient::default())
+    }
+
+    /// Create a new client that only ever talks to the given endpoint. No
+    /// discovery or environment variables are consulted, so a misconfigured
+    /// endpoint fails instead of silently reaching a different tailscaled.
+    pub fn new_with_endpoint(endpoint: Endpoint) -> Self {
+        let client = endpoint_client(&endpoint);
+        Self::new_with_client(DiscoveredClient {
+            cached: Arc::new(Mutex::new(Some((endpoint, client)))),
+            pinned: true,
+            socket_paths: None,
+        })
+    }
+
+    /// Create a new client that finds the local tailscaled automatically,
+    /// searching the given socket paths instead of [`DEFAULT_SOCKET_PATHS`].
+    pub fn new_from_environment_with_socket_paths<I, P>(socket_paths: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        Self::new_with_client(DiscoveredClient {
+            socket_paths: Some(socket_paths.into_iter().map(Into::into).collect()),
+            ..DiscoveredClient::default()
+        })
+    }
+
+    /// Find the local tailscaled again, replacing the cached endpoint. This is
+    /// safe to call while other requests are in flight. Clients created with
+    /// [`LocalApi::new_with_endpoint`] keep their endpoint.
+    pub async fn rediscover(&self) -> Result<Endpoint> {
+        self.client.rediscover().await
+    }
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+
+use chrono::{Duration as ChronoDuration, Utc};
+
+use crate::{BackendState, LocalApi, LocalApiClient, Status};
+
+/// tailscaled versions older than this are reported as a warning, since they
+/// lack some of the newer endpoints
+const RECOMMENDED_VERSION: (u32, u32) = (1, 70);
+
+/// Key expiry closer than this is reported as a warning
+const KEY_EXPIRY_WARNING_DAYS: i64 = 14;
+
+/// Outcome of a single [`DoctorReport`] check, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "pass",
+            Self::Warn => "warn",
+            Self::Fail => "fail",
+        })
+    }
+}
+
+/// Result of one check run by [`LocalApi::doctor`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// Short name of the check, such as `"connection"`
+    pub name: &'static str,
+    pub status: CheckStatus,
+    /// What was found, for showing to users
+    pub message: String,
+}
+
+/// Results of the checks run by [`LocalApi::doctor`]. The `Display` impl
+/// prints one check per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// Worst status of any check
+    pub fn status(&self) -> CheckStatus {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass)
+    }
+
+    /// Checks that did not pass
+    pub fn problems(&self) -> impl Iterator<Item = &Check> {
+        self.checks
+            .iter()
+            .filter(|check| check.status != CheckStatus::Pass)
+    }
+
+    fn push(&mut self, name: &'static str, status: CheckStatus, message: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            status,
+            message: message.into(),
+        });
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "[{}] {}: {}", check.status, check.name, check.message)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: LocalApiClient> LocalApi<T> {
+    /// Check the local tailscaled for common problems, like
+    /// `tailscale doctor`, for showing in an app's diagnostics screen. This
+    /// covers the connection to tailscaled, its version and backend state,
+    /// health warnings, DERP, MagicDNS, HTTPS certificates and key expiry.
+    /// Only the connection check runs if tailscaled cannot be reached.
+    pub async fn doctor(&self) -> DoctorReport {
+        let mut report = DoctorReport { checks: Vec::new() };
+        let status = match self.status().await {
+            Ok(status) => status,
+            Err(e) => {
+                report.push("connection", CheckStatus::Fail, e.to_string());
+                return report;
+            }
+        };
+        report.push("connection", CheckStatus::Pass, "connected to tailscaled");
+        check_status(&mut report, &status);
+
+        report
+    }
+}
+
+fn check_status(report: &mut DoctorReport, status: &Status) {
+    let (major, minor) = RECOMMENDED_VERSION;
+    if status.version_at_least(major, minor) {
+        report.push("version", CheckStatus::Pass, &status.version);
+    } else {
+        report.push(
+            "version",
+            CheckStatus::Warn,
+            format!(
+                "{} is older than {major}.{minor}, some endpoints may be missing",
+                status.version
+            ),
+        );
+    }
+
+    match status.backend_state {
+        BackendState::Running => report.push("backend", CheckStatus::Pass, "running"),
+        BackendState::Starting => report.push("backend", CheckStatus::Warn, "starting"),
+        state => report.push("backend", CheckStatus::Fail, format!("{state:?}")),
+    }
+
+    if status.health.is_empty() {
+        report.push("health", CheckStatus::Pass, "no warnings");
+    } else {
+        report.push("health", CheckStatus::Warn, status.health.join("; "));
+    }
+
+    if status.self_status.relay.is_empty() {
+        report.push("derp", CheckStatus::Warn, "no home DERP region");
+    } else {
+        report.push(
+            "derp",
+            CheckStatus::Pass,
+            format!("home region {}", status.self_status.relay),
+        );
+    }
+
+    match &status.current_tailnet {
+        Some(tailnet) if tailnet.magic_dns_enabled => report.push(
+            "magicdns",
+            CheckStatus::Pass,
+            format!("enabled with suffix {}", tailnet.magic_dns_suffix),
+        ),
+        Some(_) => report.push("magicdns", CheckStatus::Warn, "disabled for the tailnet"),
+        None => report.push("magicdns", CheckStatus::Warn, "not in a tailnet"),
+    }
+
+    if status.cert_domains.is_empty() {
+        report.push(
+            "certificates",
+            CheckStatus::Warn,
+            "HTTPS is not enabled for the tailnet",
+        );
+    } else {
+        report.push(
+            "certificates",
+            CheckStatus::Pass,
+            status.cert_domains.join(", "),
+        );
+    }
+
+    match status.self_status.key_expiry {
+        None => report.push("key expiry", CheckStatus::Pass, "key expiry disabled"),
+        Some(expiry) if expiry <= Utc::now() => report.push(
+            "key expiry",
+            CheckStatus::Fail,
+            format!("expired at {expiry}"),
+        ),
+        Some(expiry) if expiry - Utc::now() < ChronoDuration::days(KEY_EXPIRY_WARNING_DAYS) => {
+            report.push(
+                "key expiry",
+                CheckStatus::Warn,
+                format!("expires at {expiry}"),
+            )
+        }
+        Some(expiry) => report.push(
+            "key expiry",
+            CheckStatus::Pass,
+            format!("expires at {expiry}"),
+        ),
+    }
+}
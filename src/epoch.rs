@@ -0,0 +1,141 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use crate::Result;
+
+type ReconnectHook = Box<dyn Fn(u64) + Send + Sync>;
+
+/// Count of reconnections to tailscaled, shared by clones of a
+/// [`crate::LocalApi`]. The epoch goes up when a request succeeds after an
+/// earlier one failed to reach tailscaled, such as while it restarts.
+#[derive(Default)]
+pub(crate) struct ConnectionEpoch {
+    epoch: AtomicU64,
+    disconnected: AtomicBool,
+    hooks: Mutex<Vec<ReconnectHook>>,
+}
+
+impl ConnectionEpoch {
+    pub(crate) fn current(&self) -> u64 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn add_hook(&self, hook: ReconnectHook) {
+        self.hooks.lock().unwrap().push(hook);
+    }
+
+    /// Note the outcome of a request, starting a new epoch and running the
+    /// hooks if it reconnected after a connection failure.
+    pub(crate) fn record<R>(&self, result: &Result<R>) {
+        match result {
+            Err(e) if e.is_connection_error() => self.disconnected.store(true, Ordering::SeqCst),
+            Err(_) => {}
+            Ok(_) => {
+                if self.disconnected.swap(false, Ordering::SeqCst) {
+                    let epoch = self.epoch.fetch_add(1, Ordering::SeqCst) + 1;
+                    for hook in self.hooks.lock().unwrap().iter() {
+                        hook(epoch);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ConnectionEpoch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionEpoch")
+            .field("epoch", &self.current())
+            .field("disconnected", &self.disconnected.load(Ordering::SeqCst))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{atomic::AtomicUsize, Arc};
+
+    use async_trait::async_trait;
+    use http::{Request, Response, Uri};
+    use hyper::Body;
+
+    use super::*;
+    use crate::{Error, LocalApi, LocalApiClient};
+
+    fn hooked(epoch: &ConnectionEpoch) -> Arc<Mutex<Vec<u64>>> {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook_seen = seen.clone();
+        epoch.add_hook(Box::new(move |epoch| hook_seen.lock().unwrap().push(epoch)));
+        seen
+    }
+
+    #[test]
+    fn epochs_advance_only_after_reconnecting() {
+        let epoch = ConnectionEpoch::default();
+        let seen = hooked(&epoch);
+
+        epoch.record(&Ok(()));
+        epoch.record::<()>(&Err(Error::Timeout));
+        epoch.record(&Ok(()));
+        assert_eq!(epoch.current(), 0);
+
+        epoch.record::<()>(&Err(Error::DaemonNotFound));
+        epoch.record::<()>(&Err(Error::DaemonNotFound));
+        assert_eq!(epoch.current(), 0);
+        epoch.record(&Ok(()));
+        epoch.record(&Ok(()));
+        assert_eq!(epoch.current(), 1);
+
+        epoch.record::<()>(&Err(Error::DaemonNotFound));
+        // Other failures do not count as reaching tailscaled again
+        epoch.record::<()>(&Err(Error::Timeout));
+        assert_eq!(epoch.current(), 1);
+        epoch.record(&Ok(()));
+        assert_eq!(epoch.current(), 2);
+        assert_eq!(*seen.lock().unwrap(), [1, 2]);
+    }
+
+    /// Fails to connect while `down` is set
+    #[derive(Clone, Default)]
+    struct Flaky {
+        down: Arc<AtomicBool>,
+        requests: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl LocalApiClient for Flaky {
+        async fn request(&self, _request: Request<Body>) -> Result<Response<Body>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                return Err(Error::DaemonNotFound);
+            }
+            Ok(Response::new(Body::from("{}")))
+        }
+    }
+
+    #[tokio::test]
+    async fn clones_share_the_epoch_and_hooks() {
+        let client = Flaky::default();
+        let api = LocalApi::new_with_client(client.clone());
+        let clone = api.clone();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook_seen = seen.clone();
+        api.on_reconnect(move |epoch| hook_seen.lock().unwrap().push(epoch));
+
+        let uri = Uri::from_static("/localapi/v0/status");
+        client.down.store(true, Ordering::SeqCst);
+        assert!(api.get(uri.clone()).await.is_err());
+        client.down.store(false, Ordering::SeqCst);
+        clone.get(uri.clone()).await.unwrap();
+
+        assert_eq!(client.requests.load(Ordering::SeqCst), 2);
+        assert_eq!(api.connection_epoch(), 1);
+        assert_eq!(clone.connection_epoch(), 1);
+        assert_eq!(*seen.lock().unwrap(), [1]);
+    }
+}
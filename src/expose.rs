@@ -0,0 +1,135 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use axum::Router;
+use tokio::{net::TcpListener, task::JoinHandle};
+
+use crate::{
+    DnsName, Error, HttpHandler, LocalApi, LocalApiClient, Result, ServeConfig, TcpPortHandler,
+    WebServerConfig,
+};
+
+/// Where and how [`expose_http`] publishes a router
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    /// HTTPS port on the node, 443 by default
+    pub port: u16,
+    /// Mount point of the router, `/` by default
+    pub mount: String,
+    /// Also make the router reachable from the internet with funnel
+    pub funnel: bool,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        Self {
+            port: 443,
+            mount: "/".to_string(),
+            funnel: false,
+        }
+    }
+}
+
+/// Router published on the tailnet by [`expose_http`]. The router keeps
+/// running until [`ExposedHttp::unexpose`] is called; dropping this without
+/// calling it stops the router but leaves the serve config in place.
+pub struct ExposedHttp<T: LocalApiClient> {
+    api: LocalApi<T>,
+    /// URL the router is reachable at, such as `https://host.tailnet.ts.net/`
+    pub url: String,
+    /// Local address tailscaled proxies to
+    pub local_addr: SocketAddr,
+    host_port: String,
+    options: ServeOptions,
+    server: JoinHandle<()>,
+}
+
+/// Serve an axum router on a local port and publish it on the tailnet over
+/// HTTPS with `tailscale serve`. tailscaled terminates TLS with the node's
+/// certificate and proxies requests to the router. Other entries in the
+/// serve config are kept.
+pub async fn expose_http<T: LocalApiClient>(
+    api: &LocalApi<T>,
+    router: Router,
+    options: ServeOptions,
+) -> Result<ExposedHttp<T>> {
+    let status = api.status().await?;
+    if status.self_status.dnsname.is_empty() || status.cert_domains.is_empty() {
+        return Err(Error::HttpsNotEnabled);
+    }
+    let dnsname: DnsName = status.self_status.dnsname.parse()?;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let local_addr = listener.local_addr()?;
+    let server = tokio::spawn(async move {
+        let _ = axum::serve(listener, router).await;
+    });
+
+    let host_port = dnsname.host_port(options.port);
+    let mut change = ServeConfig::default();
+    change.tcp.insert(
+        options.port,
+        TcpPortHandler {
+            https: true,
+            ..TcpPortHandler::default()
+        },
+    );
+    let mut web = WebServerConfig::default();
+    web.handlers.insert(
+        options.mount.clone(),
+        HttpHandler {
+            proxy: format!("http://{local_addr}"),
+            ..HttpHandler::default()
+        },
+    );
+    change.web.insert(host_port.clone(), web);
+    if options.funnel {
+        change.allow_funnel.insert(host_port.clone(), true);
+    }
+
+    let mut config = api.serve_config().await?;
+    config.merge(&change);
+    if let Err(e) = api.set_serve_config(&config).await {
+        server.abort();
+        return Err(e);
+    }
+
+    let url = match options.port {
+        443 => format!("https://{dnsname}{}", options.mount),
+        port => format!("https://{dnsname}:{port}{}", options.mount),
+    };
+
+    Ok(ExposedHttp {
+        api: api.clone(),
+        url,
+        local_addr,
+        host_port,
+        options,
+        server,
+    })
+}
+
+impl<T: LocalApiClient> ExposedHttp<T> {
+    /// Remove the router from the serve config and stop it. The port and
+    /// funnel entries are removed too once nothing else is served on them.
+    pub async fn unexpose(self) -> Result<()> {
+        let mut config = self.api.serve_config().await?;
+        if let Some(web) = config.web.get_mut(&self.host_port) {
+            web.handlers.remove(&self.options.mount);
+            if web.handlers.is_empty() {
+                config.web.remove(&self.host_port);
+                config.tcp.remove(&self.options.port);
+                config.allow_funnel.remove(&self.host_port);
+            }
+        }
+        let result = self.api.set_serve_config(&config).await;
+        self.server.abort();
+
+        result
+    }
+}
+
+impl<T: LocalApiClient> Drop for ExposedHttp<T> {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use base64::Engine;
+use http::{header::CONTENT_TYPE, HeaderValue, Method, Request, Response, StatusCode};
+use hyper::{
+    body::{Bytes, HttpBody},
+    Body,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{check_status, EndpointKind, Error, LocalApi, LocalApiClient, Result};
+
+/// Response saved by a [`RecordingClient`] and served by a [`ReplayClient`]
+#[derive(Serialize, Deserialize, Debug)]
+struct Fixture {
+    method: String,
+    uri: String,
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// Body as text, when it is valid UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    /// Body as base64, when it is not valid UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_base64: Option<String>,
+}
+
+/// Client that saves every successful response from another client to a
+/// directory, for replaying later with [`ReplayClient`]. Each fixture is
+/// named after the request method, path, and query, so a repeated request
+/// replaces the earlier fixture. Streaming endpoints such as the IPN bus,
+/// Taildrop file downloads, and bodies over 1 MiB are passed through without
+/// being saved.
+///
+/// Fixtures hold the responses exactly as tailscaled sent them, including
+/// private keys from [`crate::Certs::pair`], so treat them like the
+/// secrets they may contain. Request headers, and so passwords, are never
+/// saved.
+#[derive(Clone, Debug)]
+pub struct RecordingClient<T: LocalApiClient> {
+    inner: T,
+    dir: Arc<PathBuf>,
+}
+
+impl<T: LocalApiClient> RecordingClient<T> {
+    /// Record responses from the given client to a directory, which must
+    /// exist.
+    pub fn new<P: Into<PathBuf>>(inner: T, dir: P) -> Self {
+        Self {
+            inner,
+            dir: Arc::new(dir.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: LocalApiClient> LocalApiClient for RecordingClient<T> {
+    async fn request(&self, request: Request<Body>) -> Result<Response<Body>> {
+        let method = request.method().clone();
+        let uri = request.uri().clone();
+        let response = self.inner.request(request).await?;
+        if !response.status().is_success()
+            || EndpointKind::of(uri.path()).is_streaming()
+            || is_file_download(uri.path())
+            || response.body().size_hint().lower() > MAX_FIXTURE_BODY as u64
+        {
+            return Ok(response);
+        }
+
+        let (parts, body) = response.into_parts();
+        let body = match read_fixture_body(body).await? {
+            Ok(body) => body,
+            Err(body) => return Ok(Response::from_parts(parts, body)),
+        };
+        let (text, base64) = match std::str::from_utf8(&body) {
+            Ok(text) => (Some(text.to_string()), None),
+            Err(_) => (
+                None,
+                Some(base64::engine::general_purpose::STANDARD.encode(&body)),
+            ),
+        };
+        let fixture = Fixture {
+            method: method.to_string(),
+            uri: uri.to_string(),
+            status: parts.status.as_u16(),
+            content_type: parts
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            body: text,
+            body_base64: base64,
+        };
+        let path = self.dir.join(fixture_name(&method, &uri.to_string()));
+        let contents = serde_json::to_vec_pretty(&fixture)?;
+        tokio::task::spawn_blocking(move || std::fs::write(path, contents))
+            .await
+            .map_err(|e| Error::IoError(e.into()))??;
+
+        Ok(Response::from_parts(parts, Body::from(body)))
+    }
+}
+
+/// Client that answers requests from fixtures saved by a
+/// [`RecordingClient`] instead of talking to tailscaled, for deterministic
+/// tests and reproducing bugs from a user's recording. A request without a
+/// fixture fails with [`Error::MissingFixture`].
+#[derive(Clone, Debug)]
+pub struct ReplayClient {
+    dir: Arc<PathBuf>,
+}
+
+impl ReplayClient {
+    /// Replay the fixtures in the given directory
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: Arc::new(dir.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl LocalApiClient for ReplayClient {
+    async fn request(&self, request: Request<Body>) -> Result<Response<Body>> {
+        let path = self
+            .dir
+            .join(fixture_name(request.method(), &request.uri().to_string()));
+        let fixture = tokio::task::spawn_blocking(move || read_fixture(&path))
+            .await
+            .map_err(|e| Error::IoError(e.into()))??;
+
+        let body = match (fixture.body, fixture.body_base64) {
+            (Some(text), _) => Body::from(text),
+            (None, Some(encoded)) => Body::from(
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            ),
+            (None, None) => Body::empty(),
+        };
+        let mut response = Response::new(body);
+        *response.status_mut() = StatusCode::from_u16(fixture.status).map_err(http::Error::from)?;
+        if let Some(content_type) = fixture.content_type {
+            response.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::try_from(content_type).map_err(http::Error::from)?,
+            );
+        }
+
+        check_status(response).await
+    }
+}
+
+/// Largest response body saved as a fixture
+const MAX_FIXTURE_BODY: usize = 1024 * 1024;
+
+/// Whether a request downloads a waiting Taildrop file, rather than listing
+/// them
+fn is_file_download(path: &str) -> bool {
+    path.strip_prefix("/localapi/v0/files/")
+        .is_some_and(|name| !name.is_empty())
+}
+
+/// Read a body to save as a fixture. A body over [`MAX_FIXTURE_BODY`] is
+/// handed back unsaved, with the chunks read so far followed by the rest.
+async fn read_fixture_body(mut body: Body) -> Result<std::result::Result<Bytes, Body>> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = body.data().await.transpose()? {
+        if buffer.len() + chunk.len() <= MAX_FIXTURE_BODY {
+            buffer.extend_from_slice(&chunk);
+            continue;
+        }
+
+        let (mut sender, passthrough) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in [Bytes::from(buffer), chunk] {
+                if sender.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+            while let Some(chunk) = body.data().await {
+                let Ok(chunk) = chunk else {
+                    return sender.abort();
+                };
+                if sender.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+        });
+        return Ok(Err(passthrough));
+    }
+
+    Ok(Ok(buffer.into()))
+}
+
+fn read_fixture(path: &Path) -> Result<Fixture> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(Error::MissingFixture(path.to_path_buf()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(serde_json::from_slice(&contents)?)
+}
+
+/// File name for the fixture of a request, such as `GET_status.json` or
+/// `GET_whois_addr=100.64.0.1_0-8292f913dc43f45a.json`. Characters that
+/// cannot be kept are replaced with `_`, so the name then ends with a hash
+/// of the full URI to keep distinct requests apart.
+fn fixture_name(method: &Method, uri: &str) -> String {
+    let endpoint = uri.strip_prefix("/localapi/v0/").unwrap_or(uri);
+    let mut replaced = false;
+    let name: String = endpoint
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '.' | '=' => c,
+            _ => {
+                replaced = true;
+                '_'
+            }
+        })
+        .collect();
+    let name = name.trim_matches('_');
+
+    if replaced {
+        format!("{method}_{name}-{:016x}.json", fnv1a(endpoint.as_bytes()))
+    } else {
+        format!("{method}_{name}.json")
+    }
+}
+
+/// 64-bit FNV-1a hash, which unlike the std hashers is stable between
+/// releases, so fixture names stay the same
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl<T: LocalApiClient> LocalApi<T> {
+    /// Save every successful response to a directory with a
+    /// [`RecordingClient`], keeping the other settings of this client.
+    pub fn recording<P: Into<PathBuf>>(self, dir: P) -> LocalApi<RecordingClient<T>> {
+        LocalApi {
+            client: RecordingClient::new(self.client, dir),
+            invalid_utf8: self.invalid_utf8,
+            timeouts: self.timeouts,
+            epoch: self.epoch,
+        }
+    }
+}
+
+impl LocalApi<ReplayClient> {
+    /// Create a client that answers from fixtures saved by
+    /// [`LocalApi::recording`] instead of talking to tailscaled.
+    pub fn new_with_replay_dir<P: Into<PathBuf>>(dir: P) -> Self {
+        Self::new_with_client(ReplayClient::new(dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixture_names_keep_distinct_requests_apart() {
+        assert_eq!(
+            fixture_name(&Method::GET, "/localapi/v0/status"),
+            "GET_status.json"
+        );
+        assert_eq!(
+            fixture_name(&Method::GET, "/localapi/v0/whois?addr=100.64.0.1:0"),
+            "GET_whois_addr=100.64.0.1_0-8292f913dc43f45a.json"
+        );
+
+        let query = fixture_name(&Method::GET, "/localapi/v0/whois?addr=a");
+        let path = fixture_name(&Method::GET, "/localapi/v0/whois/addr=a");
+        assert!(query.starts_with("GET_whois_addr=a-"));
+        assert_ne!(query, path);
+        assert_eq!(
+            query,
+            fixture_name(&Method::GET, "/localapi/v0/whois?addr=a")
+        );
+    }
+
+    #[test]
+    fn file_downloads_are_not_recorded() {
+        assert!(is_file_download("/localapi/v0/files/report.pdf"));
+        assert!(!is_file_download("/localapi/v0/files/"));
+        assert!(!is_file_download("/localapi/v0/file-targets"));
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_passed_through() {
+        let body = read_fixture_body(Body::from("small")).await.unwrap();
+        assert_eq!(body.unwrap(), "small");
+
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..3 {
+                let chunk = Bytes::from(vec![b'x'; MAX_FIXTURE_BODY / 2]);
+                if sender.send_data(chunk).await.is_err() {
+                    return;
+                }
+            }
+        });
+        let passthrough = read_fixture_body(body).await.unwrap().unwrap_err();
+        let passthrough = hyper::body::to_bytes(passthrough).await.unwrap();
+        assert_eq!(passthrough.len(), MAX_FIXTURE_BODY / 2 * 3);
+    }
+}
@@ -0,0 +1,111 @@
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use petgraph::graph::{NodeIndex, UnGraph};
+
+use crate::{PeerStatus, Status};
+
+/// Device in a [`TailnetGraph`]
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub id: String,
+    pub hostname: String,
+    pub tailscale_ips: Vec<IpAddr>,
+    pub online: bool,
+}
+
+impl From<&PeerStatus> for Device {
+    fn from(peer: &PeerStatus) -> Self {
+        Self {
+            id: peer.id.clone(),
+            hostname: peer.hostname.clone(),
+            tailscale_ips: peer.tailscale_ips.clone(),
+            online: peer.online,
+        }
+    }
+}
+
+/// How traffic between the local node and a peer currently flows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathKind {
+    /// Direct connection to the given endpoint
+    Direct(String),
+    /// Relayed through the given DERP region
+    Derp(String),
+    /// No traffic has been sent recently
+    Idle,
+}
+
+/// Edge in a [`TailnetGraph`] between the local node and a peer
+#[derive(Debug, Clone)]
+pub struct Path {
+    pub kind: PathKind,
+    /// Round trip time, when known from a ping
+    pub latency: Option<Duration>,
+}
+
+/// Undirected graph with the local node and its peers as nodes and the
+/// current paths between them as edges
+#[derive(Debug, Clone)]
+pub struct TailnetGraph {
+    pub graph: UnGraph<Device, Path>,
+    /// Index of the local node
+    pub self_node: NodeIndex,
+    nodes: HashMap<String, NodeIndex>,
+}
+
+impl TailnetGraph {
+    /// Index of the device with the given node ID
+    pub fn node(&self, id: &str) -> Option<NodeIndex> {
+        self.nodes.get(id).copied()
+    }
+
+    /// Record the round trip time to a peer. Returns `false` if the peer is
+    /// not in the graph.
+    pub fn set_latency(&mut self, id: &str, latency: Duration) -> bool {
+        let Some(edge) = self
+            .node(id)
+            .and_then(|node| self.graph.find_edge(self.self_node, node))
+        else {
+            return false;
+        };
+
+        self.graph[edge].latency = Some(latency);
+        true
+    }
+}
+
+impl From<&Status> for TailnetGraph {
+    fn from(status: &Status) -> Self {
+        let mut graph = UnGraph::new_undirected();
+        let mut nodes = HashMap::new();
+        let self_node = graph.add_node(Device::from(&status.self_status));
+        nodes.insert(status.self_status.id.clone(), self_node);
+
+        for peer in status.peer.values() {
+            let node = graph.add_node(Device::from(peer));
+            nodes.insert(peer.id.clone(), node);
+
+            let kind = if !peer.cur_addr.is_empty() {
+                PathKind::Direct(peer.cur_addr.clone())
+            } else if peer.active && !peer.relay.is_empty() {
+                PathKind::Derp(peer.relay.clone())
+            } else {
+                PathKind::Idle
+            };
+            graph.add_edge(
+                self_node,
+                node,
+                Path {
+                    kind,
+                    latency: None,
+                },
+            );
+        }
+
+        Self {
+            graph,
+            self_node,
+            nodes,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+use crate::{Error, Result};
+
+/// Sample from a Prometheus text exposition, such as one line of
+/// [`crate::Debugging::usermetrics`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricSample {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+/// Parse the samples in a Prometheus text exposition. Comments, including
+/// `HELP` and `TYPE` lines, and timestamps are skipped.
+pub fn parse_metrics(text: &str) -> Result<Vec<MetricSample>> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_sample(line).ok_or_else(|| Error::InvalidMetrics(line.to_string())))
+        .collect()
+}
+
+fn parse_sample(line: &str) -> Option<MetricSample> {
+    let name_end = line.find(|c: char| c == '{' || c.is_whitespace())?;
+    let (name, mut rest) = line.split_at(name_end);
+    let mut labels = BTreeMap::new();
+    if let Some(after_brace) = rest.strip_prefix('{') {
+        rest = after_brace;
+        loop {
+            rest = rest.trim_start_matches([',', ' ']);
+            if let Some(after) = rest.strip_prefix('}') {
+                rest = after;
+                break;
+            }
+
+            let (label, after_name) = rest.split_once('=')?;
+            let (value, after_value) = parse_label_value(after_name.strip_prefix('"')?)?;
+            labels.insert(label.trim().to_string(), value);
+            rest = after_value;
+        }
+    }
+
+    // A timestamp may follow the value
+    let value = rest.split_whitespace().next()?.parse().ok()?;
+    Some(MetricSample {
+        name: name.to_string(),
+        labels,
+        value,
+    })
+}
+
+/// Parse a quoted label value after its opening quote, returning the value
+/// and the rest of the line after the closing quote.
+fn parse_label_value(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[index + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                escaped => value.push(escaped),
+            },
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_with_labels() {
+        let samples = parse_metrics(
+            "# HELP tailscaled_inbound_bytes_total Bytes received\n\
+             # TYPE tailscaled_inbound_bytes_total counter\n\
+             tailscaled_inbound_bytes_total{path=\"direct_ipv4\"} 1024\n\
+             \n\
+             tailscaled_health_messages{type=\"warning\", severity=\"low\",} 2\n\
+             tailscaled_advertised_routes 0\n",
+        )
+        .unwrap();
+
+        assert_eq!(samples.len(), 3);
+        assert_eq!(samples[0].name, "tailscaled_inbound_bytes_total");
+        assert_eq!(samples[0].labels["path"], "direct_ipv4");
+        assert_eq!(samples[0].value, 1024.0);
+        assert_eq!(samples[1].labels.len(), 2);
+        assert_eq!(samples[1].labels["severity"], "low");
+        assert!(samples[2].labels.is_empty());
+    }
+
+    #[test]
+    fn label_values_are_unescaped() {
+        let samples =
+            parse_metrics(r#"metric{message="say \"hi\"\nthen \\ leave",empty=""} 1"#).unwrap();
+
+        assert_eq!(samples[0].labels["message"], "say \"hi\"\nthen \\ leave");
+        assert_eq!(samples[0].labels["empty"], "");
+    }
+
+    #[test]
+    fn timestamps_are_skipped() {
+        let samples =
+            parse_metrics("metric{a=\"b\"} 3.5 1700000000000\nother 4 1700000000000").unwrap();
+
+        assert_eq!(samples[0].value, 3.5);
+        assert_eq!(samples[1].value, 4.0);
+    }
+
+    #[test]
+    fn special_values() {
+        let samples = parse_metrics("a +Inf\nb -Inf\nc NaN\nd 1e3").unwrap();
+
+        assert_eq!(samples[0].value, f64::INFINITY);
+        assert_eq!(samples[1].value, f64::NEG_INFINITY);
+        assert!(samples[2].value.is_nan());
+        assert_eq!(samples[3].value, 1000.0);
+    }
+
+    #[test]
+    fn malformed_lines_are_invalid() {
+        for line in [
+            "metric",
+            "metric{a=\"b\"}",
+            "metric twelve",
+            "metric{a=\"unterminated} 1",
+            "metric{a=b} 1",
+            "metric{a=\"b\"",
+        ] {
+            match parse_metrics(&format!("good 1\n{line}\n")) {
+                Err(Error::InvalidMetrics(invalid)) => assert_eq!(invalid, line),
+                result => panic!("{line}: {result:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+/// Rolling window of DERP latencies per region, for spotting degraded relays.
+/// Feed it the per-region latencies of each netcheck report as they come in.
+#[derive(Debug, Clone)]
+pub struct DerpLatencyMonitor {
+    window: usize,
+    regions: BTreeMap<i32, VecDeque<Duration>>,
+}
+
+impl DerpLatencyMonitor {
+    /// Create a monitor that keeps the last `window` samples of each region.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            regions: BTreeMap::new(),
+        }
+    }
+
+    /// Record the latencies of one report, keyed by DERP region ID. Regions
+    /// missing from the report keep their previous samples.
+    pub fn record<I>(&mut self, latencies: I)
+    where
+        I: IntoIterator<Item = (i32, Duration)>,
+    {
+        for (region, latency) in latencies {
+            let samples = self.regions.entry(region).or_default();
+            if samples.len() == self.window {
+                samples.pop_front();
+            }
+            samples.push_back(latency);
+        }
+    }
+
+    /// IDs of the regions with samples
+    pub fn regions(&self) -> impl Iterator<Item = i32> + '_ {
+        self.regions.keys().copied()
+    }
+
+    /// Samples of a region, oldest first
+    pub fn samples(&self, region: i32) -> impl Iterator<Item = Duration> + '_ {
+        self.regions.get(&region).into_iter().flatten().copied()
+    }
+
+    /// Latency below which the given fraction of a region's samples fall,
+    /// using the nearest rank. `percentile` is clamped to `0.0..=1.0`, so
+    /// `0.5` is the median and `0.99` the 99th percentile.
+    pub fn percentile(&self, region: i32, percentile: f64) -> Option<Duration> {
+        let mut samples: Vec<Duration> = self.samples(region).collect();
+        if samples.is_empty() {
+            return None;
+        }
+
+        samples.sort();
+        let rank = (percentile.clamp(0.0, 1.0) * samples.len() as f64).ceil() as usize;
+        Some(samples[rank.saturating_sub(1)])
+    }
+
+    /// Ratio of the mean latency in the newer half of a region's window to
+    /// the older half. Values above 1.0 mean the region is getting slower.
+    /// With an odd number of samples the middle one belongs to neither half.
+    /// Returns `None` until the region has at least two samples, or when the
+    /// older half averages zero.
+    pub fn trend(&self, region: i32) -> Option<f64> {
+        let samples: Vec<Duration> = self.samples(region).collect();
+        if samples.len() < 2 {
+            return None;
+        }
+
+        let half = samples.len() / 2;
+        let (older, newer) = (&samples[..half], &samples[samples.len() - half..]);
+        let mean = |samples: &[Duration]| {
+            samples.iter().map(Duration::as_secs_f64).sum::<f64>() / samples.len() as f64
+        };
+        let older = mean(older);
+        (older > 0.0).then(|| mean(newer) / older)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(window: usize, millis: &[u64]) -> DerpLatencyMonitor {
+        let mut monitor = DerpLatencyMonitor::new(window);
+        for &millis in millis {
+            monitor.record([(1, Duration::from_millis(millis))]);
+        }
+        monitor
+    }
+
+    #[test]
+    fn window_keeps_the_newest_samples() {
+        let mut monitor = monitor(3, &[10, 20, 30, 40]);
+        monitor.record([(2, Duration::from_millis(5))]);
+
+        let samples: Vec<u128> = monitor.samples(1).map(|d| d.as_millis()).collect();
+        assert_eq!(samples, [20, 30, 40]);
+        assert_eq!(monitor.regions().collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(monitor.samples(3).count(), 0);
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let monitor = monitor(5, &[50, 10, 40, 20, 30]);
+        let percentile = |p| monitor.percentile(1, p).unwrap().as_millis();
+
+        assert_eq!(percentile(0.5), 30);
+        assert_eq!(percentile(0.99), 50);
+        assert_eq!(percentile(0.2), 10);
+        assert_eq!(percentile(0.21), 20);
+        assert_eq!(percentile(0.0), 10);
+        assert_eq!(percentile(-1.0), 10);
+        assert_eq!(percentile(2.0), 50);
+        assert_eq!(monitor.percentile(2, 0.5), None);
+    }
+
+    #[test]
+    fn trend_compares_the_halves_of_the_window() {
+        assert_eq!(monitor(4, &[10, 10, 20, 20]).trend(1), Some(2.0));
+        assert_eq!(monitor(4, &[20, 20, 10, 10]).trend(1), Some(0.5));
+        assert_eq!(monitor(4, &[10]).trend(1), None);
+        assert_eq!(monitor(4, &[0, 0, 10, 10]).trend(1), None);
+    }
+
+    #[test]
+    fn trend_skips_the_middle_of_an_odd_window() {
+        assert_eq!(monitor(3, &[10, 100, 30]).trend(1), Some(3.0));
+        assert_eq!(monitor(5, &[10, 30, 1000, 20, 40]).trend(1), Some(1.5));
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    error::Error as _,
+    fmt,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use http::{uri::PathAndQuery, Request, Response, Uri, Version};
+use hyper::{
+    client::connect::{Connected, Connection},
+    service::Service,
+    Body, Client,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::UnixStream,
+};
+
+use crate::{accept_json, check_status, unix_connect_error, wire, Error, LocalApiClient, Result};
+
+/// Client that connects to the local tailscaled over a unix socket using
+/// hyper's pooled client. Connections are kept alive and reused between
+/// requests, unlike [`crate::UnixStreamClient`] which connects per request.
+#[derive(Clone)]
+pub struct PooledUnixStreamClient {
+    client: Client<UnixConnector, Body>,
+    socket_path: Arc<PathBuf>,
+}
+
+impl PooledUnixStreamClient {
+    /// Create a builder for a pooled client connecting to the socket at the
+    /// given path.
+    pub fn builder<P: AsRef<Path>>(socket_path: P) -> PooledUnixStreamClientBuilder {
+        PooledUnixStreamClientBuilder {
+            socket_path: socket_path.as_ref().to_path_buf(),
+            builder: Client::builder(),
+        }
+    }
+}
+
+impl fmt::Debug for PooledUnixStreamClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledUnixStreamClient")
+            .field("socket_path", &self.socket_path)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl LocalApiClient for PooledUnixStreamClient {
+    async fn request(&self, mut request: Request<Body>) -> Result<Response<Body>> {
+        // The pooled client routes on the URI authority, which also becomes
+        // the Host header tailscaled expects.
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .cloned()
+            .unwrap_or_else(|| PathAndQuery::from_static("/"));
+        *request.uri_mut() = Uri::builder()
+            .scheme("http")
+            .authority("local-tailscaled.sock")
+            .path_and_query(path_and_query)
+            .build()?;
+        *request.version_mut() = Version::HTTP_11;
+        accept_json(request.headers_mut());
+
+        let request = wire::dump_request(request).await?;
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|e| self.request_error(e))?;
+        let response = wire::dump_response(response).await?;
+        check_status(response).await
+    }
+}
+
+impl PooledUnixStreamClient {
+    /// Report connection failures the same way as the other transports.
+    fn request_error(&self, error: hyper::Error) -> Error {
+        if !error.is_connect() {
+            return error.into();
+        }
+
+        match error
+            .source()
+            .and_then(|source| source.downcast_ref::<io::Error>())
+        {
+            Some(io_error) => unix_connect_error(
+                &self.socket_path,
+                io::Error::new(io_error.kind(), io_error.to_string()),
+            ),
+            None => error.into(),
+        }
+    }
+}
+
+/// Builder for [`PooledUnixStreamClient`]
+pub struct PooledUnixStreamClientBuilder {
+    socket_path: PathBuf,
+    builder: hyper::client::Builder,
+}
+
+impl PooledUnixStreamClientBuilder {
+    /// Set how long idle connections are kept in the pool. Pass `None` to keep
+    /// them until the daemon closes them.
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.builder.pool_idle_timeout(timeout);
+        self
+    }
+
+    /// Set the maximum number of idle connections kept in the pool. Setting
+    /// this to zero disables keep-alive.
+    pub fn pool_max_idle(mut self, max_idle: usize) -> Self {
+        self.builder.pool_max_idle_per_host(max_idle);
+        self
+    }
+
+    /// Set the maximum buffer size used when reading HTTP/1 responses.
+    pub fn http1_max_buf_size(mut self, max: usize) -> Self {
+        self.builder.http1_max_buf_size(max);
+        self
+    }
+
+    /// Send HTTP/1 request headers in title case.
+    pub fn http1_title_case_headers(mut self, enabled: bool) -> Self {
+        self.builder.http1_title_case_headers(enabled);
+        self
+    }
+
+    /// Build the client. The client spawns its connection tasks on the
+    /// current tokio runtime. Connections are opened as requests need them,
+    /// or up front with [`crate::LocalApi::connect_eagerly`].
+    pub fn build(mut self) -> PooledUnixStreamClient {
+        let socket_path = Arc::new(self.socket_path);
+        let connector = UnixConnector {
+            socket_path: socket_path.clone(),
+        };
+        let client = self.builder.executor(TokioExecutor).build(connector);
+
+        PooledUnixStreamClient {
+            client,
+            socket_path,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TokioExecutor;
+
+impl<F> hyper::rt::Executor<F> for TokioExecutor
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, future: F) {
+        tokio::spawn(future);
+    }
+}
+
+#[derive(Clone)]
+struct UnixConnector {
+    socket_path: Arc<PathBuf>,
+}
+
+impl Service<Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UnixConnection>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let socket_path = self.socket_path.clone();
+        Box::pin(async move {
+            let stream = UnixStream::connect(&*socket_path).await?;
+            Ok(UnixConnection(stream))
+        })
+    }
+}
+
+struct UnixConnection(UnixStream);
+
+impl Connection for UnixConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for UnixConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,474 @@
+use std::{future, future::Future, net::IpAddr, pin::Pin, task::Poll, time::Duration, vec};
+
+use crate::{join_all, LocalApi, LocalApiClient, PingResult, PingType, Result, Status};
+
+/// Pings a set of peers several times each and summarizes how they were
+/// reached, for periodic network quality reports. Peers are pinged
+/// concurrently, and the pings to each peer are sent one after another.
+#[derive(Clone, Debug)]
+pub struct ReachabilityProbe<T: LocalApiClient> {
+    api: LocalApi<T>,
+    peers: Vec<IpAddr>,
+    count: usize,
+    ping_type: PingType,
+    interval: Duration,
+}
+
+impl<T: LocalApiClient> ReachabilityProbe<T> {
+    /// Create a probe with no peers that sends 5 disco pings to each peer,
+    /// 200 milliseconds apart.
+    pub fn new(api: LocalApi<T>) -> Self {
+        Self {
+            api,
+            peers: Vec::new(),
+            count: 5,
+            ping_type: PingType::Disco,
+            interval: Duration::from_millis(200),
+        }
+    }
+
+    /// Add peers to ping by Tailscale IP
+    pub fn peers<I: IntoIterator<Item = IpAddr>>(mut self, peers: I) -> Self {
+        self.peers.extend(peers);
+        self
+    }
+
+    /// Add every online peer in the status, using each peer's first
+    /// Tailscale IP
+    pub fn online_peers(self, status: &Status) -> Self {
+        let peers = status
+            .peer
+            .values()
+            .filter(|peer| peer.online)
+            .filter_map(|peer| peer.tailscale_ips.first().copied());
+        self.peers(peers)
+    }
+
+    /// Set how many pings are sent to each peer
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = count.max(1);
+        self
+    }
+
+    /// Set the type of ping sent
+    pub fn ping_type(mut self, ping_type: PingType) -> Self {
+        self.ping_type = ping_type;
+        self
+    }
+
+    /// Set the time between pings to the same peer
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Ping the peers and summarize the results. Pings that fail, whether
+    /// unanswered or because the request to tailscaled failed, count as lost.
+    pub async fn run(&self) -> ReachabilityReport {
+        let peers = join_all(self.peers.iter().map(|&ip| self.probe(ip))).await;
+        ReachabilityReport { peers }
+    }
+
+    async fn probe(&self, ip: IpAddr) -> PeerReachability {
+        let mut peer = PeerReachability {
+            ip,
+            node_name: String::new(),
+            sent: 0,
+            received: 0,
+            direct: 0,
+            relayed: 0,
+            endpoint: None,
+            derp_region: None,
+            last_error: None,
+            rtts: Vec::new(),
+        };
+
+        for attempt in 0..self.count {
+            if attempt > 0 {
+                tokio::time::sleep(self.interval).await;
+            }
+
+            peer.sent += 1;
+            let result = match self.api.ping(ip, self.ping_type).await {
+                Ok(result) => result,
+                Err(e) => {
+                    peer.last_error = Some(e.to_string());
+                    continue;
+                }
+            };
+            let Some(latency) = result.latency() else {
+                peer.last_error = Some(result.err);
+                continue;
+            };
+
+            peer.received += 1;
+            peer.rtts.push(latency);
+            let direct = result.is_direct();
+            if !result.node_name.is_empty() {
+                peer.node_name = result.node_name;
+            }
+            if direct {
+                peer.direct += 1;
+                peer.endpoint = Some(result.endpoint);
+            } else {
+                peer.relayed += 1;
+                peer.derp_region = Some(result.derp_region_code);
+            }
+        }
+        peer.rtts.sort();
+
+        peer
+    }
+}
+
+/// Results of a [`ReachabilityProbe`], one entry per peer in the order they
+/// were added
+#[derive(Clone, Debug)]
+pub struct ReachabilityReport {
+    pub peers: Vec<PeerReachability>,
+}
+
+impl ReachabilityReport {
+    /// Peers that answered no pings
+    pub fn unreachable(&self) -> impl Iterator<Item = &PeerReachability> {
+        self.peers.iter().filter(|peer| peer.received == 0)
+    }
+}
+
+/// How one peer was reached during a [`ReachabilityProbe`]
+#[derive(Clone, Debug)]
+pub struct PeerReachability {
+    pub ip: IpAddr,
+    /// Name of the node that answered, empty if none did
+    pub node_name: String,
+    /// Number of pings sent
+    pub sent: usize,
+    /// Number of pings answered
+    pub received: usize,
+    /// Number of answers over a direct connection
+    pub direct: usize,
+    /// Number of answers relayed through DERP
+    pub relayed: usize,
+    /// Endpoint of the last direct answer
+    pub endpoint: Option<String>,
+    /// DERP region code of the last relayed answer
+    pub derp_region: Option<String>,
+    /// Why the last lost ping failed
+    pub last_error: Option<String>,
+    rtts: Vec<Duration>,
+}
+
+impl PeerReachability {
+    /// Fraction of pings that were lost, from `0.0` to `1.0`
+    pub fn loss(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+
+        (self.sent - self.received) as f64 / self.sent as f64
+    }
+
+    /// Round trip times of the answered pings, fastest first
+    pub fn rtts(&self) -> &[Duration] {
+        &self.rtts
+    }
+
+    /// Round trip time below which the given fraction of answers fall, using
+    /// the nearest rank. `percentile` is clamped to `0.0..=1.0`.
+    pub fn rtt_percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.rtts.is_empty() {
+            return None;
+        }
+
+        let rank = (percentile.clamp(0.0, 1.0) * self.rtts.len() as f64).ceil() as usize;
+        Some(self.rtts[rank.saturating_sub(1)])
+    }
+}
+
+impl<T: LocalApiClient> LocalApi<T> {
+    /// Ping every online peer once, with at most `concurrency` pings in
+    /// flight, for tools such as tailnet weather maps. Results are yielded as
+    /// the pings finish, and each peer is pinged at its first Tailscale IP.
+    pub async fn ping_all_peers(
+        &self,
+        ping_type: PingType,
+        concurrency: usize,
+    ) -> Result<PingSweep<'_, T>> {
+        let status = self.status().await?;
+        let peers: Vec<IpAddr> = status
+            .peer
+            .values()
+            .filter(|peer| peer.online)
+            .filter_map(|peer| peer.tailscale_ips.first().copied())
+            .collect();
+
+        Ok(PingSweep {
+            api: self,
+            ping_type,
+            concurrency: concurrency.max(1),
+            pending: peers.into_iter(),
+            in_flight: Vec::new(),
+        })
+    }
+}
+
+type PingFuture<'a> = Pin<Box<dyn Future<Output = (IpAddr, Result<PingResult>)> + Send + 'a>>;
+
+/// Pings to every online peer, from [`LocalApi::ping_all_peers`]. Pings only
+/// make progress while [`PingSweep::next`] is awaited.
+pub struct PingSweep<'a, T: LocalApiClient> {
+    api: &'a LocalApi<T>,
+    ping_type: PingType,
+    concurrency: usize,
+    pending: vec::IntoIter<IpAddr>,
+    in_flight: Vec<PingFuture<'a>>,
+}
+
+impl<'a, T: LocalApiClient> PingSweep<'a, T> {
+    /// Wait for the next ping to finish and return the IP it was sent to
+    /// with its result. Returns `None` once every peer has been pinged.
+    pub async fn next(&mut self) -> Option<(IpAddr, Result<PingResult>)> {
+        while self.in_flight.len() < self.concurrency {
+            let Some(ip) = self.pending.next() else {
+                break;
+            };
+            let (api, ping_type) = (self.api, self.ping_type);
+            self.in_flight
+                .push(Box::pin(async move { (ip, api.ping(ip, ping_type).await) }));
+        }
+
+        if self.in_flight.is_empty() {
+            return None;
+        }
+
+        let (index, result) = future::poll_fn(|cx| {
+            for (index, ping) in self.in_flight.iter_mut().enumerate() {
+                if let Poll::Ready(result) = ping.as_mut().poll(cx) {
+                    return Poll::Ready((index, result));
+                }
+            }
+            Poll::Pending
+        })
+        .await;
+        // The finished ping has nothing left to poll
+        drop(self.in_flight.swap_remove(index));
+
+        Some(result)
+    }
+
+    /// Number of peers not yet pinged or still waiting for an answer
+    pub fn remaining(&self) -> usize {
+        self.pending.len() + self.in_flight.len()
+    }
+}
+
+impl<T: LocalApiClient> std::fmt::Debug for PingSweep<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PingSweep")
+            .field("ping_type", &self.ping_type)
+            .field("concurrency", &self.concurrency)
+            .field("remaining", &self.remaining())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, VecDeque},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use async_trait::async_trait;
+    use http::{Request, Response};
+    use hyper::Body;
+
+    use super::*;
+    use crate::Error;
+
+    /// Answers pings from a script per IP, where `None` fails the request.
+    /// Each answer takes as long as its latency, and the most pings in flight
+    /// at once are tracked.
+    #[derive(Clone, Default)]
+    struct ScriptedPings {
+        answers: Arc<Mutex<HashMap<String, VecDeque<Option<serde_json::Value>>>>>,
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl ScriptedPings {
+        fn script(self, ip: &str, answers: Vec<Option<serde_json::Value>>) -> Self {
+            self.answers
+                .lock()
+                .unwrap()
+                .insert(ip.to_string(), answers.into());
+            self
+        }
+    }
+
+    #[async_trait]
+    impl LocalApiClient for ScriptedPings {
+        async fn request(&self, request: Request<Body>) -> Result<Response<Body>> {
+            let query = request.uri().query().unwrap();
+            let ip = query
+                .strip_prefix("ip=")
+                .and_then(|rest| rest.split('&').next())
+                .unwrap();
+            let answer = self
+                .answers
+                .lock()
+                .unwrap()
+                .get_mut(ip)
+                .unwrap()
+                .pop_front()
+                .flatten();
+
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            let latency = answer
+                .as_ref()
+                .and_then(|answer| answer["LatencySeconds"].as_f64())
+                .unwrap_or(0.0);
+            tokio::time::sleep(Duration::from_secs_f64(latency)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            match answer {
+                Some(answer) => Ok(Response::new(Body::from(serde_json::to_vec(&answer)?))),
+                None => Err(Error::Timeout),
+            }
+        }
+    }
+
+    fn direct(millis: u64) -> Option<serde_json::Value> {
+        Some(serde_json::json!({
+            "NodeName": "peer",
+            "LatencySeconds": millis as f64 / 1000.0,
+            "Endpoint": "192.0.2.1:41641",
+        }))
+    }
+
+    fn relayed(millis: u64) -> Option<serde_json::Value> {
+        Some(serde_json::json!({
+            "LatencySeconds": millis as f64 / 1000.0,
+            "DERPRegionCode": "nyc",
+        }))
+    }
+
+    fn unanswered() -> Option<serde_json::Value> {
+        Some(serde_json::json!({"Err": "timeout waiting for pong"}))
+    }
+
+    fn with_rtts(millis: &[u64]) -> PeerReachability {
+        PeerReachability {
+            ip: IpAddr::from([100, 64, 0, 1]),
+            node_name: String::new(),
+            sent: millis.len(),
+            received: millis.len(),
+            direct: 0,
+            relayed: 0,
+            endpoint: None,
+            derp_region: None,
+            last_error: None,
+            rtts: millis.iter().copied().map(Duration::from_millis).collect(),
+        }
+    }
+
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let peer = with_rtts(&[10, 20, 30, 40, 50, 60, 70, 80, 90, 100]);
+        let percentile = |p| peer.rtt_percentile(p).unwrap().as_millis();
+
+        assert_eq!(percentile(0.5), 50);
+        assert_eq!(percentile(0.9), 90);
+        assert_eq!(percentile(0.91), 100);
+        assert_eq!(percentile(0.05), 10);
+    }
+
+    #[test]
+    fn percentile_edge_cases() {
+        assert_eq!(with_rtts(&[]).rtt_percentile(0.5), None);
+        assert_eq!(with_rtts(&[]).loss(), 0.0);
+
+        let single = with_rtts(&[25]);
+        for p in [0.0, 0.5, 1.0] {
+            assert_eq!(single.rtt_percentile(p), Some(Duration::from_millis(25)));
+        }
+
+        let peer = with_rtts(&[10, 20, 30]);
+        let percentile = |p| peer.rtt_percentile(p).unwrap().as_millis();
+        assert_eq!(percentile(0.0), 10);
+        assert_eq!(percentile(1.0), 30);
+        assert_eq!(percentile(-1.0), 10);
+        assert_eq!(percentile(2.0), 30);
+    }
+
+    #[tokio::test]
+    async fn probes_count_lost_pings() {
+        let pings = ScriptedPings::default()
+            .script(
+                "100.64.0.1",
+                vec![direct(30), unanswered(), relayed(10), None, direct(20)],
+            )
+            .script("100.64.0.2", vec![None, unanswered()]);
+        let report = ReachabilityProbe::new(LocalApi::new_with_client(pings))
+            .peers([IpAddr::from([100, 64, 0, 1]), IpAddr::from([100, 64, 0, 2])])
+            .count(5)
+            .interval(Duration::ZERO)
+            .run()
+            .await;
+        let peer = &report.peers[0];
+        assert_eq!((peer.sent, peer.received), (5, 3));
+        assert!((peer.loss() - 0.4).abs() < f64::EPSILON);
+        assert_eq!((peer.direct, peer.relayed), (2, 1));
+        assert_eq!(peer.node_name, "peer");
+        assert_eq!(peer.endpoint.as_deref(), Some("192.0.2.1:41641"));
+        assert_eq!(peer.derp_region.as_deref(), Some("nyc"));
+        assert_eq!(peer.last_error.as_deref(), Some("timed out"));
+        assert_eq!(
+            peer.rtts(),
+            [10, 20, 30].map(Duration::from_millis).as_slice()
+        );
+
+        let unreachable: Vec<_> = report.unreachable().map(|peer| peer.ip).collect();
+        assert_eq!(unreachable, [IpAddr::from([100, 64, 0, 2])]);
+        let peer = &report.peers[1];
+        assert_eq!(peer.loss(), 1.0);
+        assert_eq!(peer.last_error.as_deref(), Some("timed out"));
+    }
+
+    #[tokio::test]
+    async fn sweeps_limit_pings_in_flight() {
+        let peers: Vec<IpAddr> = (1..=5)
+            .map(|last| IpAddr::from([100, 64, 0, last]))
+            .collect();
+        let mut pings = ScriptedPings::default();
+        for (ip, millis) in peers.iter().zip([50, 20, 40, 10, 30]) {
+            pings = pings.script(&ip.to_string(), vec![direct(millis)]);
+        }
+        let api = LocalApi::new_with_client(pings.clone());
+        let mut sweep = PingSweep {
+            api: &api,
+            ping_type: PingType::Disco,
+            concurrency: 2,
+            pending: peers.clone().into_iter(),
+            in_flight: Vec::new(),
+        };
+
+        let mut finished = Vec::new();
+        while let Some((ip, result)) = sweep.next().await {
+            assert!(result.unwrap().is_direct());
+            finished.push(ip);
+            assert_eq!(sweep.remaining(), peers.len() - finished.len());
+        }
+
+        assert_eq!(pings.max_in_flight.load(Ordering::SeqCst), 2);
+        // The second peer answers before the first, letting the third start
+        assert_eq!(finished[0], peers[1]);
+        finished.sort();
+        assert_eq!(finished, peers);
+        assert!(sweep.next().await.is_none());
+    }
+}
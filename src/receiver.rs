@@ -0,0 +1,376 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use hyper::body::Bytes;
+use tokio::sync::mpsc;
+
+use crate::{
+    taildrop::sanitize_file_name, Error, IpnBusWatcher, LocalApi, LocalApiClient, NotifyWatchOpt,
+    Result, WaitingFile,
+};
+
+/// Check run on each received file before it is saved. It reads the
+/// downloaded file, so it can hash files of any size, and runs on a blocking
+/// thread.
+pub type FileVerifier = Arc<dyn Fn(&WaitingFile, &mut dyn Read) -> io::Result<bool> + Send + Sync>;
+
+/// Chunks buffered between the download and the thread writing the file
+const WRITE_QUEUE: usize = 8;
+
+/// Something that happened to an incoming Taildrop file
+#[derive(Debug)]
+pub enum FileEvent {
+    /// The file was saved and removed from the inbox
+    Saved {
+        name: String,
+        path: PathBuf,
+        size: u64,
+    },
+    /// The file did not match its expected size or failed the verifier. It is
+    /// left in the inbox and not tried again by this receiver.
+    Rejected { name: String },
+}
+
+/// Receiver that saves incoming Taildrop files to a directory. Files already
+/// waiting are picked up first, then the IPN bus is watched for new ones.
+/// Names are sanitized with [`sanitize_file_name`], and a file that would
+/// overwrite an existing one is saved as `name (1).ext` and so on. Files are
+/// streamed to a hidden partial file in the directory and only renamed into
+/// place once complete and verified, so memory use does not grow with the
+/// size of the file.
+pub struct FileReceiver<T: LocalApiClient> {
+    api: LocalApi<T>,
+    dir: PathBuf,
+    verifier: Option<FileVerifier>,
+    watcher: Option<IpnBusWatcher>,
+    events: VecDeque<FileEvent>,
+    rejected: HashSet<String>,
+}
+
+impl<T: LocalApiClient> FileReceiver<T> {
+    /// Create a receiver saving files to the given directory, which must
+    /// exist.
+    pub fn new<P: Into<PathBuf>>(api: LocalApi<T>, dir: P) -> Self {
+        Self {
+            api,
+            dir: dir.into(),
+            verifier: None,
+            watcher: None,
+            events: VecDeque::new(),
+            rejected: HashSet::new(),
+        }
+    }
+
+    /// Check each file before saving it, for example against a checksum
+    /// sent out of band. The verifier reads the downloaded file, and files
+    /// failing the check are rejected.
+    pub fn verify_with<F>(mut self, verifier: F) -> Self
+    where
+        F: Fn(&WaitingFile, &mut dyn Read) -> io::Result<bool> + Send + Sync + 'static,
+    {
+        self.verifier = Some(Arc::new(verifier));
+        self
+    }
+
+    /// Wait for the next file to be saved or rejected. Returns
+    /// [`Error::BusClosed`] if tailscaled stops sending notifications.
+    /// Cancelling this while a file is being saved can leave the file both
+    /// saved and in the inbox, so it is saved again on the next call.
+    pub async fn next(&mut self) -> Result<FileEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+
+            // Watch before listing so files arriving in between are noticed
+            if self.watcher.is_none() {
+                self.watcher = Some(self.api.watch_ipn_bus(NotifyWatchOpt::default()).await?);
+            } else {
+                self.wait_for_files().await?;
+            }
+
+            for file in self.api.files().waiting().await? {
+                if !self.rejected.contains(&file.name) {
+                    let event = self.receive(file).await?;
+                    self.events.push_back(event);
+                }
+            }
+        }
+    }
+
+    async fn wait_for_files(&mut self) -> Result<()> {
+        let watcher = self.watcher.as_mut().expect("watcher is started");
+        while let Some(notify) = watcher.next().await? {
+            if notify.files_waiting.is_some() {
+                return Ok(());
+            }
+        }
+
+        self.watcher = None;
+        Err(Error::BusClosed)
+    }
+
+    async fn receive(&mut self, file: WaitingFile) -> Result<FileEvent> {
+        let Some(safe_name) = sanitize_file_name(&file.name) else {
+            return Ok(self.reject(file));
+        };
+
+        let mut body = self.api.files().get_stream(&file.name).await?;
+        let (chunks, queue) = mpsc::channel(WRITE_QUEUE);
+        let dir = self.dir.clone();
+        let verifier = self.verifier.clone();
+        let waiting = file.clone();
+        let writer = tokio::task::spawn_blocking(move || {
+            write_file(&dir, &safe_name, &waiting, queue, verifier.as_ref())
+        });
+
+        let download = async {
+            while let Some(chunk) = body.next_chunk().await? {
+                if chunks.send(Some(chunk)).await.is_err() {
+                    break;
+                }
+            }
+            // Tell the writer the file is complete, rather than cut short
+            let _ = chunks.send(None).await;
+            Ok::<_, Error>(())
+        }
+        .await;
+        drop(chunks);
+        let written = writer.await.map_err(|e| Error::IoError(e.into()))?;
+        download?;
+
+        let Some((path, size)) = written? else {
+            return Ok(self.reject(file));
+        };
+        self.api.files().delete(&file.name).await?;
+
+        Ok(FileEvent::Saved {
+            name: file.name,
+            path,
+            size,
+        })
+    }
+
+    fn reject(&mut self, file: WaitingFile) -> FileEvent {
+        self.rejected.insert(file.name.clone());
+        FileEvent::Rejected { name: file.name }
+    }
+}
+
+/// Write the chunks of a download to a partial file, then check it and move
+/// it into place. Returns `None` if the file was rejected. The partial file is
+/// removed unless the file is saved.
+fn write_file(
+    dir: &Path,
+    name: &str,
+    file: &WaitingFile,
+    mut chunks: mpsc::Receiver<Option<Bytes>>,
+    verifier: Option<&FileVerifier>,
+) -> io::Result<Option<(PathBuf, u64)>> {
+    let (partial, mut output) = create_unique(dir, &format!(".{name}.partial"))?;
+    let result = (|| {
+        let mut size = 0;
+        loop {
+            match chunks.blocking_recv() {
+                Some(Some(chunk)) => {
+                    output.write_all(&chunk)?;
+                    size += chunk.len() as u64;
+                }
+                Some(None) => break,
+                None => return Err(io::Error::from(io::ErrorKind::Interrupted)),
+            }
+        }
+        output.sync_all()?;
+
+        if u64::try_from(file.size) != Ok(size) {
+            return Ok(None);
+        }
+        if let Some(verifier) = verifier {
+            if !verifier(file, &mut File::open(&partial)?)? {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some((save(dir, name, &partial)?, size)))
+    })();
+
+    if !matches!(result, Ok(Some(_))) {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Move a complete file into the directory without replacing an existing
+/// one, adding a counter to the name until it is unique.
+fn save(dir: &Path, name: &str, partial: &Path) -> io::Result<PathBuf> {
+    // Reserve the name first so the rename only ever replaces this
+    // placeholder
+    let (path, _) = create_unique(dir, name)?;
+    if let Err(e) = fs::rename(partial, &path) {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+
+    Ok(path)
+}
+
+/// Create a new file, adding a counter to the name until it is unique
+fn create_unique(dir: &Path, name: &str) -> io::Result<(PathBuf, File)> {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name, None),
+    };
+
+    for counter in 0.. {
+        let candidate = match (counter, extension) {
+            (0, _) => name.to_string(),
+            (_, Some(extension)) => format!("{stem} ({counter}).{extension}"),
+            (_, None) => format!("{stem} ({counter})"),
+        };
+        let path = dir.join(candidate);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    unreachable!("ran out of file name counters")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, env, process, sync::Mutex};
+
+    use async_trait::async_trait;
+    use http::{Method, Request, Response};
+    use hyper::Body;
+
+    use super::*;
+    use crate::taildrop::unescape_file_name;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tailscale-localapi-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn save_adds_a_counter_to_taken_names() {
+        let dir = temp_dir("save");
+        for name in ["photo.jpg", "README", ".bashrc"] {
+            fs::write(dir.join(name), "taken").unwrap();
+            for content in ["first", "second"] {
+                let partial = dir.join("partial");
+                fs::write(&partial, content).unwrap();
+                save(&dir, name, &partial).unwrap();
+            }
+        }
+
+        assert_eq!(
+            file_names(&dir),
+            [
+                ".bashrc",
+                ".bashrc (1)",
+                ".bashrc (2)",
+                "README",
+                "README (1)",
+                "README (2)",
+                "photo (1).jpg",
+                "photo (2).jpg",
+                "photo.jpg",
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("photo (2).jpg")).unwrap(),
+            "second"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Inbox of files with their declared sizes and contents
+    #[derive(Clone, Default)]
+    struct FakeInbox {
+        files: Arc<Mutex<BTreeMap<String, (i64, &'static str)>>>,
+    }
+
+    #[async_trait]
+    impl LocalApiClient for FakeInbox {
+        async fn request(&self, request: Request<Body>) -> Result<Response<Body>> {
+            let path = request.uri().path();
+            let mut files = self.files.lock().unwrap();
+            let body = match path.strip_prefix("/localapi/v0/files/") {
+                _ if path == "/localapi/v0/watch-ipn-bus" => Body::from("{}\n"),
+                Some("") => {
+                    let waiting: Vec<_> = files
+                        .iter()
+                        .map(|(name, (size, _))| serde_json::json!({"Name": name, "Size": size}))
+                        .collect();
+                    Body::from(serde_json::to_vec(&waiting)?)
+                }
+                Some(name) => {
+                    let name = unescape_file_name(name).unwrap();
+                    if request.method() == Method::DELETE {
+                        files.remove(&name);
+                        Body::empty()
+                    } else {
+                        Body::from(files[&name].1)
+                    }
+                }
+                None => panic!("unexpected request to {path}"),
+            };
+
+            Ok(Response::new(body))
+        }
+    }
+
+    #[tokio::test]
+    async fn receiver_saves_and_rejects_files() {
+        let dir = temp_dir("receiver");
+        let inbox = FakeInbox::default();
+        inbox.files.lock().unwrap().extend([
+            ("bad.txt".to_string(), (3, "bad")),
+            ("good.txt".to_string(), (5, "hello")),
+            ("short.bin".to_string(), (10, "abc")),
+        ]);
+        let mut receiver = FileReceiver::new(LocalApi::new_with_client(inbox.clone()), &dir)
+            .verify_with(|_, reader| {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                Ok(content != "bad")
+            });
+
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            events.push(receiver.next().await.unwrap());
+        }
+
+        assert!(matches!(&events[0], FileEvent::Rejected { name } if name == "bad.txt"));
+        assert!(matches!(
+            &events[1],
+            FileEvent::Saved { name, path, size: 5 }
+                if name == "good.txt" && *path == dir.join("good.txt")
+        ));
+        assert!(matches!(&events[2], FileEvent::Rejected { name } if name == "short.bin"));
+        assert_eq!(file_names(&dir), ["good.txt"]);
+        assert_eq!(fs::read_to_string(dir.join("good.txt")).unwrap(), "hello");
+        let left: Vec<String> = inbox.files.lock().unwrap().keys().cloned().collect();
+        assert_eq!(left, ["bad.txt", "short.bin"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
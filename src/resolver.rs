@@ -0,0 +1,51 @@
+use std::net::IpAddr;
+
+use hickory_proto::{
+    op::{Message, ResponseCode},
+    rr::RecordType,
+};
+
+use crate::{DnsName, Error, LocalApi, LocalApiClient, Result};
+
+/// Resolver that sends every query through tailscaled, so names such as
+/// `host.tailnet.ts.net` and split DNS domains resolve the same way they do
+/// for other programs on the node
+#[derive(Clone, Debug)]
+pub struct Resolver<T: LocalApiClient> {
+    api: LocalApi<T>,
+}
+
+impl<T: LocalApiClient> Resolver<T> {
+    /// Create a resolver using the given client
+    pub fn new(api: LocalApi<T>) -> Self {
+        Self { api }
+    }
+
+    /// Send a query and parse the response message.
+    pub async fn lookup(&self, name: &DnsName, record_type: RecordType) -> Result<Message> {
+        let response = self.api.dns_query(name, &record_type.to_string()).await?;
+        Message::from_vec(&response.bytes).map_err(|_| Error::InvalidDnsResponse)
+    }
+
+    /// Look up the IPv4 and IPv6 addresses of a name. A name that does not
+    /// exist resolves to no addresses.
+    pub async fn lookup_ip(&self, name: &DnsName) -> Result<Vec<IpAddr>> {
+        let mut addresses = Vec::new();
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            let message = self.lookup(name, record_type).await?;
+            match message.metadata.response_code {
+                ResponseCode::NoError | ResponseCode::NXDomain => {}
+                code => return Err(Error::DnsQueryFailed(code.to_string())),
+            }
+
+            addresses.extend(
+                message
+                    .answers
+                    .iter()
+                    .filter_map(|record| record.data.ip_addr()),
+            );
+        }
+
+        Ok(addresses)
+    }
+}
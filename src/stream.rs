@@ -0,0 +1,42 @@
+use hyper::{
+    body::{Bytes, HttpBody},
+    Body,
+};
+
+use std::{future::Future, time::Duration};
+
+use crate::{Error, Result};
+
+/// Stream of raw bytes from a long running response, such as a packet
+/// capture. The connection stays open until the stream is dropped.
+pub struct ByteStream {
+    body: Body,
+    idle_timeout: Option<Duration>,
+}
+
+impl ByteStream {
+    pub(crate) fn new(body: Body, idle_timeout: Option<Duration>) -> Self {
+        Self { body, idle_timeout }
+    }
+
+    /// Wait for the next chunk of data. Returns `None` once tailscaled ends
+    /// the response, or [`crate::Error::Timeout`] if no data arrives within
+    /// the idle timeout.
+    pub async fn next_chunk(&mut self) -> Result<Option<Bytes>> {
+        match idle(self.idle_timeout, self.body.data()).await? {
+            Some(chunk) => Ok(Some(chunk?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Wait for the next piece of a stream, giving up once it has been idle for
+/// too long.
+pub(crate) async fn idle<F: Future>(timeout: Option<Duration>, next: F) -> Result<F::Output> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, next)
+            .await
+            .map_err(|_| Error::Timeout),
+        None => Ok(next.await),
+    }
+}
@@ -0,0 +1,554 @@
+use std::{
+    future::{self, Future},
+    pin::pin,
+    task::Poll,
+};
+
+use http::{
+    header::{CONTENT_LENGTH, RANGE},
+    Method, Request, StatusCode, Uri,
+};
+use hyper::{body::HttpBody, Body};
+use tokio::{io::AsyncRead, time};
+
+use crate::{
+    file_put_uri, file_uri, parse_metrics, query_escape, read_secret, read_text, taildrop, within,
+    ByteStream, Certificate, DnsName, Error, FileTarget, LocalApi, LocalApiClient, LoginProfile,
+    MetricSample, NetworkLockStatus, PprofProfile, PrivateKey, ReadBody, Result, TransferProgress,
+    WaitingFile, MAX_CERTIFICATE_SIZE,
+};
+
+impl<T: LocalApiClient> LocalApi<T> {
+    /// Endpoints for the node's HTTPS certificates
+    pub fn certs(&self) -> Certs<'_, T> {
+        Certs { api: self }
+    }
+
+    /// Endpoints for sending and receiving Taildrop files
+    pub fn files(&self) -> Files<'_, T> {
+        Files { api: self }
+    }
+
+    /// Endpoints for the login profiles of the node, like `tailscale switch`
+    pub fn profiles(&self) -> Profiles<'_, T> {
+        Profiles { api: self }
+    }
+
+    /// Endpoints for tailnet lock, like `tailscale lock`
+    pub fn lock(&self) -> NetworkLock<'_, T> {
+        NetworkLock { api: self }
+    }
+
+    /// Endpoints for diagnosing tailscaled
+    pub fn debug(&self) -> Debugging<'_, T> {
+        Debugging { api: self }
+    }
+}
+
+/// HTTPS certificate endpoints, from [`LocalApi::certs`]
+#[derive(Debug)]
+pub struct Certs<'a, T: LocalApiClient> {
+    api: &'a LocalApi<T>,
+}
+
+impl<T: LocalApiClient> Certs<'_, T> {
+    /// Get the certificate and key for a domain. The domain should be one of
+    /// the valid domains for the local node. It is normalized as a
+    /// [`DnsName`], so a trailing dot or uppercase letters are accepted.
+    ///
+    /// The response must hold exactly one private key, in any position
+    /// relative to the certificates, which are returned in the order sent.
+    /// More than one key is reported as [`Error::MultiplePrivateKeys`] rather
+    /// than guessing which one belongs to the leaf certificate.
+    pub async fn pair(&self, domain: &str) -> Result<(PrivateKey, Vec<Certificate>)> {
+        let domain: DnsName = domain.parse()?;
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!("/localapi/v0/cert/{domain}?type=pair"))
+            .body(Body::empty())?;
+        let timeout = self.api.timeouts.for_path(request.uri().path());
+        // The body holds the PEM encoded key, so it is read straight into a
+        // buffer that is wiped once parsed
+        let body = within(timeout, async {
+            let response = self
+                .api
+                .request_with_timeout(request, None, ReadBody::Streaming)
+                .await?;
+            read_secret(response.into_body(), MAX_CERTIFICATE_SIZE).await
+        })
+        .await?;
+        let items = rustls_pemfile::read_all(&mut &body[..])?;
+
+        let mut certificates = Vec::new();
+        let mut private_keys = Vec::new();
+        for item in items {
+            match item {
+                rustls_pemfile::Item::ECKey(data)
+                | rustls_pemfile::Item::PKCS8Key(data)
+                | rustls_pemfile::Item::RSAKey(data) => private_keys.push(PrivateKey::new(data)),
+                rustls_pemfile::Item::X509Certificate(data) => certificates.push(Certificate(data)),
+                _ => return Err(Error::UnknownCertificateOrKey),
+            }
+        }
+        if private_keys.len() > 1 {
+            return Err(Error::MultiplePrivateKeys(private_keys.len()));
+        }
+        let private_key = private_keys.pop().ok_or(Error::UnknownCertificateOrKey)?;
+
+        Ok((private_key, certificates))
+    }
+
+    /// Get the certificate and key for a domain as rustls 0.21 types, like
+    /// [`Certs::pair`].
+    #[cfg(feature = "rustls-021")]
+    pub async fn pair_rustls_021(
+        &self,
+        domain: &str,
+    ) -> Result<(rustls_021::PrivateKey, Vec<rustls_021::Certificate>)> {
+        let (key, certificates) = self.pair(domain).await?;
+        Ok((
+            key.into(),
+            certificates.into_iter().map(Into::into).collect(),
+        ))
+    }
+
+    /// Get the certificate and key for a domain as `rustls-pki-types` types,
+    /// used by rustls 0.22 and later, like [`Certs::pair`].
+    #[cfg(feature = "pki-types")]
+    pub async fn pair_pki_types(
+        &self,
+        domain: &str,
+    ) -> Result<(
+        rustls_pki_types::PrivateKeyDer<'static>,
+        Vec<rustls_pki_types::CertificateDer<'static>>,
+    )> {
+        let (key, certificates) = self.pair(domain).await?;
+        Ok((
+            key.try_into()?,
+            certificates.into_iter().map(Into::into).collect(),
+        ))
+    }
+}
+
+/// Taildrop endpoints, from [`LocalApi::files`]
+#[derive(Debug)]
+pub struct Files<'a, T: LocalApiClient> {
+    api: &'a LocalApi<T>,
+}
+
+impl<T: LocalApiClient> Files<'_, T> {
+    /// List the peers that can currently receive Taildrop files, such as for
+    /// a "send to" picker.
+    pub async fn targets(&self) -> Result<Vec<FileTarget>> {
+        let response = self
+            .api
+            .get(Uri::from_static("/localapi/v0/file-targets"))
+            .await?;
+        let targets: Option<Vec<FileTarget>> = self.api.read_json("file-targets", response).await?;
+
+        Ok(targets.unwrap_or_default())
+    }
+
+    /// List the Taildrop files waiting to be picked up.
+    pub async fn waiting(&self) -> Result<Vec<WaitingFile>> {
+        let response = self
+            .api
+            .get(Uri::from_static("/localapi/v0/files/"))
+            .await?;
+        let files: Option<Vec<WaitingFile>> = self.api.read_json("files", response).await?;
+
+        Ok(files.unwrap_or_default())
+    }
+
+    /// Download a waiting Taildrop file.
+    pub async fn get(&self, name: &str) -> Result<Vec<u8>> {
+        let response = self.api.get(file_uri(name)).await?;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        Ok(body.to_vec())
+    }
+
+    /// Download a waiting Taildrop file as a stream of chunks, for files too
+    /// large to hold in memory. The slow timeout covers waiting for the
+    /// download to start, and [`crate::Timeouts::stream_idle`] the gaps between
+    /// chunks.
+    pub async fn get_stream(&self, name: &str) -> Result<ByteStream> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(file_uri(name))
+            .body(Body::empty())?;
+        let response = self
+            .api
+            .request_with_timeout(request, self.api.timeouts.slow, ReadBody::Streaming)
+            .await?;
+
+        Ok(ByteStream::new(
+            response.into_body(),
+            self.api.timeouts.stream_idle,
+        ))
+    }
+
+    /// Download part of a waiting Taildrop file, starting at `offset` and
+    /// reading at most `length` bytes. This allows resuming interrupted
+    /// downloads and verifying large files chunk by chunk. If tailscaled
+    /// ignores the range and sends the whole file, the bytes before `offset`
+    /// are skipped as they arrive and the download stops once the requested
+    /// part has been read, so only that part is held in memory.
+    pub async fn get_range(&self, name: &str, offset: u64, length: u64) -> Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        let last = offset.saturating_add(length - 1);
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(file_uri(name))
+            .header(RANGE, format!("bytes={offset}-{last}"))
+            .body(Body::empty())?;
+        let timeout = self.api.timeouts.for_path(request.uri().path());
+        within(timeout, async {
+            let response = self
+                .api
+                .request_with_timeout(request, None, ReadBody::Streaming)
+                .await?;
+            let skip = if response.status() == StatusCode::PARTIAL_CONTENT {
+                0
+            } else {
+                offset
+            };
+            read_range(response.into_body(), skip, length).await
+        })
+        .await
+    }
+
+    /// Delete a waiting Taildrop file.
+    pub async fn delete(&self, name: &str) -> Result<()> {
+        let request = Request::builder()
+            .method(Method::DELETE)
+            .uri(file_uri(name))
+            .body(Body::empty())?;
+        self.api.request(request).await?;
+
+        Ok(())
+    }
+
+    /// Send a file to a peer over Taildrop, like `tailscale file cp`. The
+    /// target is the stable ID of one of the nodes from
+    /// [`Files::targets`]. Use [`Files::put_reader`] to
+    /// follow the progress of the upload.
+    pub async fn put(&self, target: &str, name: &str, data: Vec<u8>) -> Result<()> {
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(file_put_uri(target, name))
+            .body(Body::from(data))?;
+        self.api.request(request).await?;
+
+        Ok(())
+    }
+
+    /// Send a file to a peer over Taildrop like [`Files::put`], but
+    /// stream it from a reader instead of holding it in memory. Exactly
+    /// `length` bytes are read and sent, and `progress` is called with the
+    /// bytes sent so far, throughput, and estimated time left after each
+    /// chunk. Transfers tailscaled makes on its own behalf are reported on
+    /// the IPN bus instead, with [`crate::NotifyFilter::outgoing_files_only`].
+    /// The upload has no overall time limit;
+    /// [`crate::Timeouts::stream_idle`] limits how long each chunk may take
+    /// instead. Once the file has been sent, [`crate::Timeouts::slow`] limits
+    /// the wait for tailscaled to answer.
+    pub async fn put_reader<R, F>(
+        &self,
+        target: &str,
+        name: &str,
+        reader: R,
+        length: u64,
+        progress: F,
+    ) -> Result<()>
+    where
+        R: AsyncRead + Unpin + Send,
+        F: FnMut(&TransferProgress) + Send,
+    {
+        let (sender, body) = Body::channel();
+        let request = Request::builder()
+            .method(Method::PUT)
+            .uri(file_put_uri(target, name))
+            .header(CONTENT_LENGTH, length)
+            .body(body)?;
+
+        let mut upload = pin!(taildrop::upload(
+            reader,
+            sender,
+            length,
+            self.api.timeouts.stream_idle,
+            progress,
+        ));
+        let mut response = pin!(self.api.request_with_timeout(request, None, ReadBody::All));
+        let mut uploaded = None;
+        let mut deadline = None;
+        let response = future::poll_fn(|cx| {
+            if uploaded.is_none() {
+                if let Poll::Ready(result) = upload.as_mut().poll(cx) {
+                    uploaded = Some(result);
+                    deadline = self
+                        .api
+                        .timeouts
+                        .slow
+                        .map(|slow| Box::pin(time::sleep(slow)));
+                }
+            }
+            if let Poll::Ready(response) = response.as_mut().poll(cx) {
+                return Poll::Ready(response);
+            }
+            match deadline.as_mut() {
+                Some(deadline) => deadline.as_mut().poll(cx).map(|()| Err(Error::Timeout)),
+                None => Poll::Pending,
+            }
+        })
+        .await;
+
+        // A failed read also fails the request, but the read error says why
+        if let Some(Err(e)) = uploaded {
+            return Err(e);
+        }
+        response?;
+
+        Ok(())
+    }
+}
+
+/// Login profile endpoints, from [`LocalApi::profiles`]
+#[derive(Debug)]
+pub struct Profiles<'a, T: LocalApiClient> {
+    api: &'a LocalApi<T>,
+}
+
+impl<T: LocalApiClient> Profiles<'_, T> {
+    /// List the login profiles, like `tailscale switch --list`.
+    pub async fn list(&self) -> Result<Vec<LoginProfile>> {
+        let response = self
+            .api
+            .get(Uri::from_static("/localapi/v0/profiles/"))
+            .await?;
+        let profiles: Option<Vec<LoginProfile>> = self.api.read_json("profiles", response).await?;
+
+        Ok(profiles.unwrap_or_default())
+    }
+
+    /// Get the profile currently in use.
+    pub async fn current(&self) -> Result<LoginProfile> {
+        let response = self
+            .api
+            .get(Uri::from_static("/localapi/v0/profiles/current"))
+            .await?;
+        self.api.read_json("profiles", response).await
+    }
+
+    /// Switch to the profile with the given ID, like `tailscale switch`.
+    pub async fn switch_to(&self, id: &str) -> Result<()> {
+        self.send(Method::POST, id).await
+    }
+
+    /// Switch to a new, logged out profile, ready for
+    /// [`LocalApi::login_interactive`] to log in to another tailnet.
+    pub async fn add(&self) -> Result<()> {
+        self.send(Method::PUT, "").await
+    }
+
+    /// Delete the profile with the given ID. Deleting the current profile
+    /// logs out first.
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        self.send(Method::DELETE, id).await
+    }
+
+    async fn send(&self, method: Method, id: &str) -> Result<()> {
+        let request = Request::builder()
+            .method(method)
+            .uri(format!(
+                "/localapi/v0/profiles/{}",
+                taildrop::escape_file_name(id)
+            ))
+            .body(Body::empty())?;
+        self.api.request(request).await?;
+
+        Ok(())
+    }
+}
+
+/// Tailnet lock endpoints, from [`LocalApi::lock`]
+#[derive(Debug)]
+pub struct NetworkLock<'a, T: LocalApiClient> {
+    api: &'a LocalApi<T>,
+}
+
+impl<T: LocalApiClient> NetworkLock<'_, T> {
+    /// Get the tailnet lock status of the node, like `tailscale lock
+    /// status`.
+    pub async fn status(&self) -> Result<NetworkLockStatus> {
+        let response = self
+            .api
+            .get(Uri::from_static("/localapi/v0/tka/status"))
+            .await?;
+        self.api.read_json("tka/status", response).await
+    }
+}
+
+/// Diagnostic endpoints, from [`LocalApi::debug`]
+#[derive(Debug)]
+pub struct Debugging<'a, T: LocalApiClient> {
+    api: &'a LocalApi<T>,
+}
+
+impl<T: LocalApiClient> Debugging<'_, T> {
+    /// Get tailscaled's internal metrics in the Prometheus text exposition
+    /// format, for exporters that re-serve them.
+    pub async fn metrics(&self) -> Result<String> {
+        let response = self
+            .api
+            .get(Uri::from_static("/localapi/v0/metrics"))
+            .await?;
+        read_text(response).await
+    }
+
+    /// Get the metrics tailscaled publishes for users, such as traffic
+    /// counters and health, parsed into samples. Use [`Debugging::metrics`]
+    /// for tailscaled's internal metrics.
+    pub async fn usermetrics(&self) -> Result<Vec<MetricSample>> {
+        let response = self
+            .api
+            .get(Uri::from_static("/localapi/v0/usermetrics"))
+            .await?;
+        parse_metrics(&read_text(response).await?)
+    }
+
+    /// Get a dump of the stacks of all of tailscaled's goroutines, for
+    /// diagnosing a hung daemon. This needs the same access as changing
+    /// settings.
+    pub async fn goroutines(&self) -> Result<String> {
+        let response = self
+            .api
+            .get(Uri::from_static("/localapi/v0/goroutines"))
+            .await?;
+        read_text(response).await
+    }
+
+    /// Fetch a Go runtime profile of tailscaled in pprof's format, ready to
+    /// write to a `.pb.gz` file for `go tool pprof`. `seconds` is how long
+    /// to record for, which a CPU profile needs and the others ignore unless
+    /// they support a delta over time. This needs the same access as changing
+    /// settings.
+    pub async fn pprof(&self, profile: PprofProfile, seconds: Option<u32>) -> Result<Vec<u8>> {
+        let mut uri = format!("/localapi/v0/pprof?name={}", profile.as_str());
+        if let Some(seconds) = seconds {
+            uri.push_str(&format!("&seconds={seconds}"));
+        }
+        let response = self.api.get(uri.parse().unwrap()).await?;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        Ok(body.to_vec())
+    }
+
+    /// Mark tailscaled's logs for a bug report, like `tailscale bugreport`,
+    /// with an optional note. Returns the `BUG-...` marker to give to
+    /// Tailscale support.
+    pub async fn bugreport(&self, note: Option<&str>) -> Result<String> {
+        let uri = match note {
+            Some(note) => format!("/localapi/v0/bugreport?note={}", query_escape(note)),
+            None => "/localapi/v0/bugreport".to_string(),
+        };
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .body(Body::empty())?;
+        let response = self.api.request(request).await?;
+        let marker = read_text(response).await?;
+
+        Ok(marker.trim().to_string())
+    }
+
+    /// Capture the packets tailscaled sends and receives. The stream yields a
+    /// pcap file, starting with its header, that can be written to disk or
+    /// fed to a pcap reader while the capture runs.
+    pub async fn capture(&self) -> Result<ByteStream> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/localapi/v0/debug-capture")
+            .body(Body::empty())?;
+        let response = self.api.request(request).await?;
+
+        Ok(ByteStream::new(
+            response.into_body(),
+            self.api.timeouts.stream_idle,
+        ))
+    }
+}
+
+/// Read `length` bytes of `body` after skipping the first `skip`, dropping
+/// the rest of the body.
+async fn read_range(mut body: Body, mut skip: u64, length: u64) -> Result<Vec<u8>> {
+    let mut range = Vec::new();
+    let mut wanted = length;
+    while wanted > 0 {
+        let Some(chunk) = body.data().await.transpose()? else {
+            break;
+        };
+        let start = usize::try_from(skip).unwrap_or(usize::MAX).min(chunk.len());
+        skip -= start as u64;
+        let end = usize::try_from(wanted)
+            .unwrap_or(usize::MAX)
+            .min(chunk.len() - start)
+            + start;
+        range.extend_from_slice(&chunk[start..end]);
+        wanted -= (end - start) as u64;
+    }
+
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::body::Bytes;
+
+    use super::*;
+
+    fn chunked(chunks: &[&'static [u8]]) -> Body {
+        let (mut sender, body) = Body::channel();
+        let chunks: Vec<_> = chunks
+            .iter()
+            .map(|chunk| Bytes::from_static(chunk))
+            .collect();
+        tokio::spawn(async move {
+            for chunk in chunks {
+                if sender.send_data(chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        body
+    }
+
+    #[tokio::test]
+    async fn ranges_are_cut_across_chunks() {
+        let body = chunked(&[b"0123", b"4567", b"89"]);
+        let range = read_range(body, 3, 4).await.unwrap();
+        assert_eq!(range, b"3456");
+    }
+
+    #[tokio::test]
+    async fn ranges_stop_at_the_end_of_the_body() {
+        let range = read_range(Body::from("0123"), 2, 10).await.unwrap();
+        assert_eq!(range, b"23");
+
+        let range = read_range(Body::from("0123"), 10, 2).await.unwrap();
+        assert!(range.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ranges_stop_reading_once_complete() {
+        let (mut sender, body) = Body::channel();
+        sender.try_send_data(Bytes::from_static(b"0123")).unwrap();
+        // The sender is kept open, so reading past the range would hang
+        let range = read_range(body, 1, 2).await.unwrap();
+        assert_eq!(range, b"12");
+        drop(sender);
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    fmt::Write,
+    future, io,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use hyper::body::{Bytes, Sender};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::{stream, Result};
+
+/// Progress of a file being sent with [`crate::Files::put_reader`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+    /// Bytes sent so far
+    pub sent: u64,
+    /// Size of the whole file in bytes
+    pub total: u64,
+    /// Time since the upload started
+    pub elapsed: Duration,
+}
+
+impl TransferProgress {
+    /// Fraction of the file sent, from `0.0` to `1.0`
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+
+        self.sent as f64 / self.total as f64
+    }
+
+    /// Average bytes sent per second so far
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+
+        self.sent as f64 / seconds
+    }
+
+    /// Estimated time until the upload finishes at the average throughput,
+    /// or `None` before anything has been sent
+    pub fn eta(&self) -> Option<Duration> {
+        let throughput = self.throughput();
+        if throughput == 0.0 {
+            return None;
+        }
+
+        let remaining = self.total.saturating_sub(self.sent) as f64;
+        Some(Duration::from_secs_f64(remaining / throughput))
+    }
+}
+
+/// Largest chunk read from the file at a time while uploading
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Escape a file name for use as a path segment in the Taildrop endpoints.
+/// This matches Go's `url.PathEscape`, which tailscaled uses to decode the
+/// name, so spaces, path separators, and non-ASCII characters round trip
+/// exactly.
+pub fn escape_file_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'~'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b':'
+            | b'='
+            | b'@' => escaped.push(byte as char),
+            _ => write!(escaped, "%{byte:02X}").unwrap(),
+        }
+    }
+
+    escaped
+}
+
+/// Decode a file name escaped with [`escape_file_name`]. Returns `None` if
+/// the escaping is malformed or the result is not valid UTF-8.
+pub fn unescape_file_name(escaped: &str) -> Option<String> {
+    let bytes = escaped.as_bytes();
+    let mut name = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = escaped.get(i + 1..i + 3)?;
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            name.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            name.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(name).ok()
+}
+
+/// Turn a received file name into one that is safe to create in a local
+/// directory. Any directory components are dropped, control characters and
+/// characters reserved on Windows are replaced with `_`, and surrounding
+/// whitespace is trimmed. Returns `None` if nothing usable is left.
+pub fn sanitize_file_name(name: &str) -> Option<String> {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let sanitized: String = base
+        .chars()
+        .map(|c| match c {
+            ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let sanitized = sanitized.trim();
+
+    match sanitized {
+        "" | "." | ".." => None,
+        _ => Some(sanitized.to_string()),
+    }
+}
+
+/// Copy exactly `length` bytes from the reader into the request body. The
+/// body is aborted if reading fails so tailscaled does not keep a truncated
+/// file.
+pub(crate) async fn upload<R, F>(
+    mut reader: R,
+    mut sender: Sender,
+    length: u64,
+    idle_timeout: Option<Duration>,
+    mut progress: F,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    F: FnMut(&TransferProgress),
+{
+    let started = Instant::now();
+    let mut sent = 0;
+    while sent < length {
+        match send_chunk(&mut reader, &mut sender, length - sent, idle_timeout).await {
+            Ok(Some(size)) => {
+                sent += size as u64;
+                progress(&TransferProgress {
+                    sent,
+                    total: length,
+                    elapsed: started.elapsed(),
+                });
+            }
+            // tailscaled stopped reading, and its response says why
+            Ok(None) => break,
+            Err(e) => {
+                sender.abort();
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the next chunk of at most `remaining` bytes and send it. Returns
+/// `None` if the body was closed.
+async fn send_chunk<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    sender: &mut Sender,
+    remaining: u64,
+    idle_timeout: Option<Duration>,
+) -> Result<Option<usize>> {
+    let size = usize::try_from(remaining)
+        .unwrap_or(usize::MAX)
+        .min(UPLOAD_CHUNK_SIZE);
+    let mut chunk = vec![0; size];
+    let mut buf = ReadBuf::new(&mut chunk);
+    stream::idle(
+        idle_timeout,
+        future::poll_fn(|cx| Pin::new(&mut *reader).poll_read(cx, &mut buf)),
+    )
+    .await??;
+    let size = buf.filled().len();
+    if size == 0 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    chunk.truncate(size);
+    let sent = stream::idle(idle_timeout, sender.send_data(Bytes::from(chunk))).await?;
+    Ok(sent.ok().map(|()| size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_matches_go_path_escape() {
+        // Outputs of Go's url.PathEscape for the same names
+        for (name, escaped) in [
+            ("report.pdf", "report.pdf"),
+            ("my file.txt", "my%20file.txt"),
+            ("a/b", "a%2Fb"),
+            ("100%.txt", "100%25.txt"),
+            ("😀.png", "%F0%9F%98%80.png"),
+            ("日本語.txt", "%E6%97%A5%E6%9C%AC%E8%AA%9E.txt"),
+            ("..", ".."),
+            ("", ""),
+            ("a-b_c.d~e", "a-b_c.d~e"),
+            ("$&+:=@", "$&+:=@"),
+            ("a;b,c?d#e", "a%3Bb%2Cc%3Fd%23e"),
+            ("copy (1)!.txt", "copy%20%281%29%21.txt"),
+        ] {
+            assert_eq!(escape_file_name(name), escaped, "{name:?}");
+            assert_eq!(unescape_file_name(escaped).as_deref(), Some(name));
+        }
+    }
+
+    #[test]
+    fn unescape_rejects_malformed_names() {
+        assert_eq!(unescape_file_name("100%"), None);
+        assert_eq!(unescape_file_name("%2"), None);
+        assert_eq!(unescape_file_name("%zz"), None);
+        assert_eq!(unescape_file_name("%FF"), None);
+        assert_eq!(unescape_file_name("%2f").as_deref(), Some("/"));
+    }
+
+    #[test]
+    fn sanitize_drops_directories_and_reserved_names() {
+        assert_eq!(sanitize_file_name("").as_deref(), None);
+        assert_eq!(sanitize_file_name(".").as_deref(), None);
+        assert_eq!(sanitize_file_name("..").as_deref(), None);
+        assert_eq!(sanitize_file_name("a/..").as_deref(), None);
+        assert_eq!(sanitize_file_name("  ").as_deref(), None);
+        assert_eq!(sanitize_file_name("../x").as_deref(), Some("x"));
+        assert_eq!(
+            sanitize_file_name("..\\..\\x.txt").as_deref(),
+            Some("x.txt")
+        );
+        assert_eq!(sanitize_file_name("/etc/passwd").as_deref(), Some("passwd"));
+        assert_eq!(sanitize_file_name("a:b?.txt").as_deref(), Some("a_b_.txt"));
+        assert_eq!(sanitize_file_name("tab\there").as_deref(), Some("tab_here"));
+        assert_eq!(
+            sanitize_file_name(" 日本語.txt ").as_deref(),
+            Some("日本語.txt")
+        );
+    }
+
+    #[test]
+    fn transfer_progress_estimates() {
+        let progress = TransferProgress {
+            sent: 25,
+            total: 100,
+            elapsed: Duration::from_secs(5),
+        };
+        assert_eq!(progress.fraction(), 0.25);
+        assert_eq!(progress.throughput(), 5.0);
+        assert_eq!(progress.eta(), Some(Duration::from_secs(15)));
+
+        let started = TransferProgress {
+            sent: 0,
+            elapsed: Duration::ZERO,
+            ..progress
+        };
+        assert_eq!(started.eta(), None);
+    }
+}
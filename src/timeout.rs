@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+/// How long requests to tailscaled may take, by kind of endpoint. Set these
+/// for a client with [`crate::LocalApi::timeouts`], or for a single call on a
+/// clone of the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Limit for requests that tailscaled answers right away, such as
+    /// status. Defaults to 10 seconds.
+    pub quick: Option<Duration>,
+    /// Limit for requests that can keep tailscaled busy for a while, such as
+    /// issuing a certificate, pinging, or logging in. Defaults to 5 minutes.
+    pub slow: Option<Duration>,
+    /// Longest gap between data on streaming endpoints such as the IPN bus
+    /// and packet captures, and between chunks of files sent with
+    /// [`crate::Files::put_reader`]. The stream itself can run
+    /// forever. Defaults to no limit, since the IPN bus can be quiet for
+    /// hours.
+    pub stream_idle: Option<Duration>,
+}
+
+impl Timeouts {
+    /// No limits at all
+    pub const NONE: Self = Self {
+        quick: None,
+        slow: None,
+        stream_idle: None,
+    };
+
+    /// Limit for the whole of a request to the given path. For streaming
+    /// endpoints this only covers waiting for the response headers.
+    pub(crate) fn for_path(&self, path: &str) -> Option<Duration> {
+        match EndpointKind::of(path) {
+            EndpointKind::Quick | EndpointKind::Streaming => self.quick,
+            EndpointKind::Slow => self.slow,
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            quick: Some(Duration::from_secs(10)),
+            slow: Some(Duration::from_secs(5 * 60)),
+            stream_idle: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EndpointKind {
+    Quick,
+    Slow,
+    Streaming,
+}
+
+impl EndpointKind {
+    /// Kind of the endpoint at the given request path
+    pub(crate) fn of(path: &str) -> Self {
+        let endpoint = path.strip_prefix("/localapi/v0/").unwrap_or(path);
+        let name = endpoint.split('/').next().unwrap_or_default();
+        match name {
+            "watch-ipn-bus" | "debug-capture" | "logtap" => Self::Streaming,
+            "cert" | "ping" | "dns-query" | "start" | "login-interactive" | "logout"
+            | "set-expiry-sooner" | "reload-config" | "bugreport" | "pprof" | "file-put" => {
+                Self::Slow
+            }
+            // Downloading a Taildrop file can take a while, listing them does
+            // not
+            "files" if endpoint.len() > "files/".len() => Self::Slow,
+            _ => Self::Quick,
+        }
+    }
+
+    pub(crate) fn is_streaming(self) -> bool {
+        self == Self::Streaming
+    }
+}
@@ -1,17 +1,18 @@
 use std::{
-    collections::HashMap,
-    net::{IpAddr, SocketAddr},
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
 };
 
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_aux::prelude::*;
 
 /// State of the backend
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[non_exhaustive]
 pub enum BackendState {
     NoState,
+    InUseOtherUser,
     NeedsLogin,
     NeedsMachineAuth,
     Stopped,
@@ -19,6 +20,38 @@ pub enum BackendState {
     Running,
 }
 
+impl BackendState {
+    /// Parse the numeric form of the state used on the IPN bus
+    fn from_number(state: u8) -> Option<Self> {
+        let state = match state {
+            0 => Self::NoState,
+            1 => Self::InUseOtherUser,
+            2 => Self::NeedsLogin,
+            3 => Self::NeedsMachineAuth,
+            4 => Self::Stopped,
+            5 => Self::Starting,
+            6 => Self::Running,
+            _ => return None,
+        };
+
+        Some(state)
+    }
+}
+
+fn deserialize_backend_state_number<'de, D>(
+    deserializer: D,
+) -> Result<Option<BackendState>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    match Option::<u8>::deserialize(deserializer)? {
+        Some(state) => BackendState::from_number(state)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown backend state {state}"))),
+        None => Ok(None),
+    }
+}
+
 /// Status of a peer
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -34,11 +67,21 @@ pub struct PeerStatus {
     pub os: String,
     #[serde(rename = "UserID")]
     pub user_id: i64,
+    /// Addresses assigned to the node itself
     #[serde(
         rename = "TailscaleIPs",
         deserialize_with = "deserialize_default_from_null"
     )]
     pub tailscale_ips: Vec<IpAddr>,
+    /// Prefixes routed to the node, such as `100.64.0.1/32`. Besides the
+    /// node's own addresses this includes any approved subnet routes and, for
+    /// the current exit node, the default routes.
+    #[serde(
+        default,
+        rename = "AllowedIPs",
+        deserialize_with = "deserialize_default_from_null"
+    )]
+    pub allowed_ips: Vec<String>,
     #[serde(default, deserialize_with = "deserialize_default_from_null")]
     pub tags: Vec<String>,
     #[serde(default, deserialize_with = "deserialize_default_from_null")]
@@ -50,6 +93,9 @@ pub struct PeerStatus {
     pub rx_bytes: i64,
     pub tx_bytes: i64,
     pub created: DateTime<Utc>,
+    /// When the node key expires, or `None` if key expiry is disabled
+    #[serde(default)]
+    pub key_expiry: Option<DateTime<Utc>>,
     pub last_write: DateTime<Utc>,
     pub last_seen: DateTime<Utc>,
     pub last_handshake: DateTime<Utc>,
@@ -79,6 +125,199 @@ pub struct PeerStatus {
     pub in_engine: bool,
 }
 
+/// Base URL of the Tailscale admin console
+const ADMIN_CONSOLE_URL: &str = "https://login.tailscale.com/admin";
+
+impl PeerStatus {
+    /// Link to this node's page in the Tailscale admin console. Returns `None`
+    /// if the node has no IPv4 Tailscale address. Links are only meaningful
+    /// for nodes using the Tailscale coordination server.
+    pub fn admin_console_url(&self) -> Option<String> {
+        let ip = self.tailscale_ips.iter().find(|ip| ip.is_ipv4())?;
+        Some(format!("{ADMIN_CONSOLE_URL}/machines/{ip}"))
+    }
+
+    /// Most specific prefix in this peer's allowed IPs that contains the given
+    /// address, or `None` if the peer does not route it.
+    pub fn routes_for_subnet(&self, ip: IpAddr) -> Option<&str> {
+        self.allowed_ips
+            .iter()
+            .filter_map(|route| Some((route.as_str(), prefix_match(route, ip)?)))
+            .max_by_key(|&(_, prefix_len)| prefix_len)
+            .map(|(route, _)| route)
+    }
+
+    /// Whether the peer runs Tailscale SSH, which is advertised by sharing
+    /// its SSH host keys
+    pub fn ssh_available(&self) -> bool {
+        !self.ssh_hostkeys.is_empty()
+    }
+
+    /// `ssh_config` stanza for connecting to this peer by its hostname, or
+    /// `None` if the peer does not run Tailscale SSH or has no DNS name.
+    pub fn ssh_config_stanza(&self) -> Option<String> {
+        let dnsname = self.dnsname.trim_end_matches('.');
+        if !self.ssh_available() || dnsname.is_empty() {
+            return None;
+        }
+
+        Some(format!("Host {}\n    HostName {dnsname}\n", self.hostname))
+    }
+
+    /// `known_hosts` lines for this peer's SSH host keys, listing its DNS
+    /// name and Tailscale IPs
+    pub fn known_hosts_lines(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tailscale_ips.iter().map(IpAddr::to_string).collect();
+        let dnsname = self.dnsname.trim_end_matches('.');
+        if !dnsname.is_empty() {
+            names.insert(0, dnsname.to_string());
+        }
+        let names = names.join(",");
+
+        self.ssh_hostkeys
+            .iter()
+            .map(|key| format!("{names} {key}"))
+            .collect()
+    }
+
+    /// Subnet routes in this peer's allowed IPs that are 4via6 routes
+    pub fn via_routes(&self) -> Vec<ViaRoute> {
+        self.allowed_ips
+            .iter()
+            .filter_map(|route| ViaRoute::parse(route))
+            .collect()
+    }
+}
+
+/// Length of the given prefix if it contains the address
+fn prefix_match(prefix: &str, ip: IpAddr) -> Option<u8> {
+    let (network, prefix_len) = prefix.split_once('/')?;
+    let prefix_len: u8 = prefix_len.parse().ok()?;
+    let matches = match (network.parse().ok()?, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) if prefix_len <= 32 => {
+            let mask = u32::MAX
+                .checked_shl(32 - u32::from(prefix_len))
+                .unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) if prefix_len <= 128 => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(prefix_len))
+                .unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    };
+
+    matches.then_some(prefix_len)
+}
+
+/// Prefix of the IPv6 range used for 4via6 subnet routes
+const VIA_PREFIX: [u8; 8] = [0xfd, 0x7a, 0x11, 0x5c, 0xa1, 0xe0, 0x0b, 0x1a];
+
+/// IPv4 subnet route from a specific site, carried as a 4via6 IPv6 route.
+/// 4via6 lets several sites advertise the same overlapping IPv4 range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ViaRoute {
+    pub site_id: u16,
+    pub ipv4: Ipv4Addr,
+    pub prefix_len: u8,
+}
+
+impl ViaRoute {
+    /// Parse a route like `fd7a:115c:a1e0:b1a:0:7:a01:100/120`. Returns `None`
+    /// if the route is not a 4via6 route.
+    pub fn parse(route: &str) -> Option<Self> {
+        let (address, prefix_len) = route.split_once('/')?;
+        let prefix_len: u8 = prefix_len.parse().ok()?;
+        if !(96..=128).contains(&prefix_len) {
+            return None;
+        }
+
+        let (site_id, ipv4) = unmap_4via6(address.parse().ok()?)?;
+        Some(Self {
+            site_id,
+            ipv4,
+            prefix_len: prefix_len - 96,
+        })
+    }
+
+    /// The IPv6 address that carries this route
+    pub fn address(&self) -> Ipv6Addr {
+        map_4via6(self.site_id, self.ipv4)
+    }
+}
+
+impl std::fmt::Display for ViaRoute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.address(), u16::from(self.prefix_len) + 96)
+    }
+}
+
+/// Map an IPv4 address at a site to its 4via6 IPv6 address
+pub fn map_4via6(site_id: u16, ipv4: Ipv4Addr) -> Ipv6Addr {
+    let mut octets = [0; 16];
+    octets[..8].copy_from_slice(&VIA_PREFIX);
+    octets[10..12].copy_from_slice(&site_id.to_be_bytes());
+    octets[12..].copy_from_slice(&ipv4.octets());
+    Ipv6Addr::from(octets)
+}
+
+/// Map a 4via6 IPv6 address back to its site ID and IPv4 address. Returns
+/// `None` if the address is not a 4via6 address.
+pub fn unmap_4via6(address: Ipv6Addr) -> Option<(u16, Ipv4Addr)> {
+    let octets = address.octets();
+    if octets[..8] != VIA_PREFIX || octets[8..10] != [0, 0] {
+        return None;
+    }
+
+    let site_id = u16::from_be_bytes([octets[10], octets[11]]);
+    let ipv4 = Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]);
+    Some((site_id, ipv4))
+}
+
+/// Approval state of the subnet routes advertised by a node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteApprovalStatus {
+    /// Routes the node advertises, from the `AdvertiseRoutes` pref
+    pub advertised: Vec<String>,
+    /// Advertised routes an admin has approved for the node
+    pub approved: Vec<String>,
+    /// Approved routes this node is currently the primary router for
+    pub primary: Vec<String>,
+    /// Advertised routes still waiting on admin approval
+    pub pending: Vec<String>,
+}
+
+impl RouteApprovalStatus {
+    /// Compare the routes a node advertises with the routes its peer status
+    /// reports as allowed and primary.
+    pub fn new<S: AsRef<str>>(advertised: &[S], peer: &PeerStatus) -> Self {
+        let advertised: Vec<String> = advertised.iter().map(|r| r.as_ref().to_string()).collect();
+        let (approved, pending) = advertised
+            .iter()
+            .cloned()
+            .partition(|route| peer.allowed_ips.contains(route));
+        let primary = advertised
+            .iter()
+            .filter(|route| peer.primary_routes.contains(route))
+            .cloned()
+            .collect();
+
+        Self {
+            advertised,
+            approved,
+            primary,
+            pending,
+        }
+    }
+
+    /// Whether any advertised route is waiting on admin approval
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
 /// Status of the current tailnet.
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
@@ -98,7 +337,10 @@ pub struct Status {
     pub backend_state: BackendState,
     #[serde(rename = "AuthURL")]
     pub auth_url: String,
-    #[serde(rename = "TailscaleIPs", deserialize_with = "deserialize_default_from_null")]
+    #[serde(
+        rename = "TailscaleIPs",
+        deserialize_with = "deserialize_default_from_null"
+    )]
     pub tailscale_ips: Vec<IpAddr>,
     #[serde(rename = "Self")]
     pub self_status: PeerStatus,
@@ -111,6 +353,236 @@ pub struct Status {
     pub peer: HashMap<String, PeerStatus>,
     #[serde(deserialize_with = "deserialize_default_from_null")]
     pub user: HashMap<i64, UserProfile>,
+    /// Whether tailscaled uses a TUN device, or `None` if it is too old to
+    /// say
+    #[serde(rename = "TUN", default)]
+    pub tun: Option<bool>,
+}
+
+/// Device in an inventory export, created with [`Status::inventory`]. All
+/// fields are flat so records can be written as CSV rows as well as JSON,
+/// with lists joined by spaces.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InventoryRecord {
+    pub id: String,
+    pub hostname: String,
+    pub dns_name: String,
+    pub os: String,
+    /// Login name of the owning user, empty for tagged devices
+    pub user: String,
+    pub tags: String,
+    pub tailscale_ips: String,
+    pub created: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub online: bool,
+    /// Whether this is the local node
+    pub is_self: bool,
+}
+
+/// Status of the local node without its peers, as returned by
+/// [`crate::LocalApi::status_without_peers`]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct LocalStatus {
+    pub version: String,
+    pub backend_state: BackendState,
+    #[serde(rename = "AuthURL")]
+    pub auth_url: String,
+    #[serde(
+        rename = "TailscaleIPs",
+        deserialize_with = "deserialize_default_from_null"
+    )]
+    pub tailscale_ips: Vec<IpAddr>,
+    #[serde(rename = "Self")]
+    pub self_status: PeerStatus,
+    #[serde(deserialize_with = "deserialize_default_from_null")]
+    pub health: Vec<String>,
+    pub current_tailnet: Option<TailnetStatus>,
+    #[serde(deserialize_with = "deserialize_default_from_null")]
+    pub cert_domains: Vec<String>,
+    /// Profile of the user owning the local node
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub user: HashMap<i64, UserProfile>,
+}
+
+impl Status {
+    /// Whether tailscaled is at least the given version, for deciding
+    /// whether an endpoint can be used before calling it. Returns `false` if
+    /// the version cannot be parsed.
+    ///
+    /// The crate does not track which version introduced each endpoint, so
+    /// the caller supplies the version it needs. Calling an endpoint the
+    /// daemon lacks fails with [`crate::Error::UnsupportedEndpoint`].
+    pub fn version_at_least(&self, major: u32, minor: u32) -> bool {
+        let mut parts = self
+            .version
+            .split(['.', '-'])
+            .map(|part| part.parse::<u32>().ok());
+        match (parts.next().flatten(), parts.next().flatten()) {
+            (Some(have_major), Some(have_minor)) => (have_major, have_minor) >= (major, minor),
+            _ => false,
+        }
+    }
+
+    /// Whether tailscaled runs in userspace networking mode without a TUN
+    /// device. Other programs then cannot reach the tailnet directly and have
+    /// to go through tailscaled's SOCKS5 or HTTP proxy if one is enabled.
+    pub fn is_userspace_networking(&self) -> bool {
+        self.tun == Some(false)
+    }
+
+    /// Name to show for the current tailnet, such as `example.com` or
+    /// `user@gmail.com`. Falls back to the MagicDNS suffix when the tailnet
+    /// has no name, and returns `None` when not logged in.
+    pub fn tailnet_display_name(&self) -> Option<&str> {
+        let tailnet = self.current_tailnet.as_ref()?;
+        if !tailnet.name.is_empty() {
+            return Some(&tailnet.name);
+        }
+
+        let suffix = tailnet.magic_dns_suffix.trim_end_matches('.');
+        (!suffix.is_empty()).then_some(suffix)
+    }
+
+    /// Compact summary of the status, similar to the output of
+    /// `tailscale status`
+    pub fn summary(&self) -> StatusSummary {
+        StatusSummary {
+            backend_state: self.backend_state,
+            tailscale_ips: self.tailscale_ips.clone(),
+            peers: self.peer.len(),
+            online_peers: self.peer.values().filter(|peer| peer.online).count(),
+            exit_node: self
+                .peer
+                .values()
+                .find(|peer| peer.exit_node)
+                .map(|peer| peer.hostname.clone()),
+            health: self.health.clone(),
+        }
+    }
+
+    /// One record per device, the local node first and then the peers
+    /// sorted by hostname, for exporting a fleet inventory
+    pub fn inventory(&self) -> Vec<InventoryRecord> {
+        let mut peers: Vec<_> = self.peer.values().collect();
+        peers.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+
+        std::iter::once((&self.self_status, true))
+            .chain(peers.into_iter().map(|peer| (peer, false)))
+            .map(|(peer, is_self)| InventoryRecord {
+                id: peer.id.clone(),
+                hostname: peer.hostname.clone(),
+                dns_name: peer.dnsname.trim_end_matches('.').to_string(),
+                os: peer.os.clone(),
+                user: self
+                    .user
+                    .get(&peer.user_id)
+                    .map(|user| user.login_name.clone())
+                    .unwrap_or_default(),
+                tags: peer.tags.join(" "),
+                tailscale_ips: peer
+                    .tailscale_ips
+                    .iter()
+                    .map(IpAddr::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                created: peer.created,
+                last_seen: peer.last_seen,
+                online: peer.online,
+                is_self,
+            })
+            .collect()
+    }
+
+    /// `ssh_config` stanzas for every peer running Tailscale SSH, sorted by
+    /// hostname
+    pub fn ssh_config(&self) -> String {
+        let mut peers: Vec<_> = self.peer.values().collect();
+        peers.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        peers
+            .into_iter()
+            .filter_map(PeerStatus::ssh_config_stanza)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Whether an address belongs to the tailnet as seen by the local node:
+    /// one of the node's own Tailscale IPs, a peer's, or inside a subnet route
+    /// a peer advertises. Exit node default routes do not count, or every
+    /// address would. IPv4 addresses mapped into IPv6, as dual stack sockets
+    /// report them, are treated as IPv4.
+    ///
+    /// Being inside the CGNAT range `100.64.0.0/10` or Tailscale's
+    /// `fd7a:115c:a1e0::/48` is not enough on its own, since ISPs use the
+    /// CGNAT range too and the addresses of other tailnets come from the same
+    /// ranges.
+    pub fn contains_ip(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        let is_route =
+            |route: &String| prefix_match(route, ip).is_some_and(|prefix_len| prefix_len > 0);
+
+        self.tailscale_ips.contains(&ip)
+            || self.peer.values().any(|peer| {
+                peer.tailscale_ips.contains(&ip) || peer.allowed_ips.iter().any(is_route)
+            })
+    }
+
+    /// Whether the node is waiting for an admin to approve it
+    pub fn needs_machine_approval(&self) -> bool {
+        self.backend_state == BackendState::NeedsMachineAuth
+    }
+
+    /// Peer that traffic to the given address is routed to, using the most
+    /// specific matching prefix across all peers' allowed IPs.
+    pub fn peer_routing(&self, ip: IpAddr) -> Option<&PeerStatus> {
+        self.peer
+            .values()
+            .filter_map(|peer| {
+                let route = peer.routes_for_subnet(ip)?;
+                Some((peer, prefix_match(route, ip)?))
+            })
+            .max_by_key(|&(_, prefix_len)| prefix_len)
+            .map(|(peer, _)| peer)
+    }
+}
+
+/// Summary of a [`Status`], created with [`Status::summary`]. The `Display`
+/// impl prints one field per line for CLI tools.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusSummary {
+    pub backend_state: BackendState,
+    pub tailscale_ips: Vec<IpAddr>,
+    /// Number of peers in the netmap
+    pub peers: usize,
+    /// Number of those peers that are online
+    pub online_peers: usize,
+    /// Hostname of the exit node in use
+    pub exit_node: Option<String>,
+    /// Health warnings reported by tailscaled
+    pub health: Vec<String>,
+}
+
+impl From<&Status> for StatusSummary {
+    fn from(status: &Status) -> Self {
+        status.summary()
+    }
+}
+
+impl std::fmt::Display for StatusSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "state: {:?}", self.backend_state)?;
+        let ips: Vec<String> = self.tailscale_ips.iter().map(IpAddr::to_string).collect();
+        writeln!(f, "ips: {}", ips.join(", "))?;
+        writeln!(f, "peers: {} ({} online)", self.peers, self.online_peers)?;
+        if let Some(exit_node) = &self.exit_node {
+            writeln!(f, "exit node: {exit_node}")?;
+        }
+        for warning in &self.health {
+            writeln!(f, "health: {warning}")?;
+        }
+
+        Ok(())
+    }
 }
 
 /// Service protocol
@@ -146,6 +618,11 @@ pub struct Hostinfo {
     pub os: Option<String>,
     #[serde(rename = "OSVersion")]
     pub os_version: Option<String>,
+    pub device_model: Option<String>,
+    pub distro: Option<String>,
+    pub distro_version: Option<String>,
+    pub package: Option<String>,
+    pub machine: Option<String>,
     pub hostname: Option<String>,
     pub services: Option<Vec<Service>>,
     #[serde(default, rename = "sshHostKeys")]
@@ -191,6 +668,16 @@ pub struct Node {
     pub computed_name_with_host: String,
 }
 
+/// Peer that can currently receive Taildrop files
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct FileTarget {
+    pub node: Node,
+    /// Base URL of the peer's PeerAPI, which files are sent to
+    #[serde(rename = "PeerAPIURL")]
+    pub peer_api_url: String,
+}
+
 /// User profile.
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "PascalCase")]
@@ -199,10 +686,100 @@ pub struct UserProfile {
     pub id: i64,
     pub login_name: String,
     pub display_name: String,
-    #[serde(rename = "ProfilePicURL")]
+    #[serde(rename = "ProfilePicURL", default)]
     pub profile_pic_url: String,
 }
 
+/// Login profile of the local node. tailscaled can hold several, such as for
+/// a work and a personal tailnet, and switch between them like `tailscale
+/// switch`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct LoginProfile {
+    /// ID used to switch to or delete the profile
+    #[serde(rename = "ID")]
+    pub id: String,
+    /// Name of the profile, usually the login name
+    #[serde(default)]
+    pub name: String,
+    /// Tailnet the profile belongs to
+    #[serde(default)]
+    pub network_profile: NetworkProfile,
+    /// User the profile is logged in as, empty for a new profile
+    pub user_profile: UserProfile,
+    /// Stable ID of the node in the tailnet
+    #[serde(rename = "NodeID", default)]
+    pub node_id: String,
+    /// URL of the control server
+    #[serde(rename = "ControlURL", default)]
+    pub control_url: String,
+}
+
+/// Tailnet of a [`LoginProfile`]
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct NetworkProfile {
+    /// MagicDNS suffix of the tailnet, such as `tailnet.ts.net`
+    #[serde(rename = "MagicDNSName", default)]
+    pub magic_dns_name: String,
+    /// Name of the tailnet
+    #[serde(default)]
+    pub domain_name: String,
+}
+
+/// Tailnet lock status of the local node, like `tailscale lock status`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct NetworkLockStatus {
+    /// Whether tailnet lock is enabled for the tailnet
+    pub enabled: bool,
+    /// Tailnet lock key of the local node, as `tlpub:...`
+    #[serde(default)]
+    pub public_key: String,
+    /// Node key of the local node
+    pub node_key: Option<String>,
+    /// Whether the local node key is signed by a trusted key
+    #[serde(default)]
+    pub node_key_signed: bool,
+    /// Keys trusted to sign nodes
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub trusted_keys: Vec<NetworkLockKey>,
+    /// Peers that are hidden because their node keys are not signed
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub filtered_peers: Vec<NetworkLockPeer>,
+}
+
+/// Key trusted by tailnet lock
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct NetworkLockKey {
+    /// The key, as `tlpub:...`
+    pub key: String,
+    /// Metadata attached to the key when it was added
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub metadata: HashMap<String, String>,
+    /// Votes the key has in tailnet lock decisions
+    #[serde(default)]
+    pub votes: u32,
+}
+
+/// Peer hidden by tailnet lock
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct NetworkLockPeer {
+    pub name: String,
+    #[serde(rename = "StableID", default)]
+    pub stable_id: String,
+    #[serde(
+        rename = "TailscaleIPs",
+        default,
+        deserialize_with = "deserialize_default_from_null"
+    )]
+    pub tailscale_ips: Vec<IpAddr>,
+    #[serde(default)]
+    pub node_key: String,
+}
+
 /// Whois response
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "PascalCase")]
@@ -213,9 +790,1846 @@ pub struct Whois {
     pub caps: Vec<String>,
 }
 
-/// DER encoded X.509 certificate for the node. This can either be the leaf
-/// certificate or part of the certificate chain.
-pub struct Certificate(pub Vec<u8>);
+impl Whois {
+    /// Operating system of the peer node, e.g. `linux` or `windows`
+    pub fn os(&self) -> Option<&str> {
+        self.node.hostinfo.os.as_deref()
+    }
+
+    /// Operating system version of the peer node
+    pub fn os_version(&self) -> Option<&str> {
+        self.node.hostinfo.os_version.as_deref()
+    }
+
+    /// Device model of the peer node, reported by mobile clients
+    pub fn device_model(&self) -> Option<&str> {
+        self.node.hostinfo.device_model.as_deref()
+    }
+}
+
+/// Configuration for `tailscale serve` and `tailscale funnel`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct ServeConfig {
+    /// Handlers for TCP ports on the node
+    #[serde(
+        rename = "TCP",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_default_from_null"
+    )]
+    pub tcp: BTreeMap<u16, TcpPortHandler>,
+    /// Web servers keyed by `host:port`
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_default_from_null"
+    )]
+    pub web: BTreeMap<String, WebServerConfig>,
+    /// Whether funnel is enabled for each `host:port`
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "deserialize_default_from_null"
+    )]
+    pub allow_funnel: BTreeMap<String, bool>,
+    /// Fields this crate does not model, such as foreground configs. These are
+    /// kept so writing a config back does not drop them.
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+    /// ETag of the config as read from tailscaled. When set, updates are
+    /// rejected if the config changed since it was read.
+    #[serde(skip)]
+    pub etag: Option<String>,
+}
+
+/// Handler for a TCP port in a [`ServeConfig`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct TcpPortHandler {
+    #[serde(rename = "HTTPS", default, skip_serializing_if = "is_false")]
+    pub https: bool,
+    #[serde(rename = "HTTP", default, skip_serializing_if = "is_false")]
+    pub http: bool,
+    #[serde(
+        rename = "TCPForward",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub tcp_forward: String,
+    #[serde(
+        rename = "TerminateTLS",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub terminate_tls: String,
+    /// Fields this crate does not model, kept so writing the config back
+    /// does not drop them
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// Web server in a [`ServeConfig`]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct WebServerConfig {
+    /// Handlers keyed by mount point
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub handlers: BTreeMap<String, HttpHandler>,
+    /// Fields this crate does not model, kept so writing the config back
+    /// does not drop them
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// HTTP handler for a mount point. Exactly one of `path`, `proxy`, and
+/// `text` should be set.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct HttpHandler {
+    /// Absolute path to a file or directory to serve
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+    /// URL of a backend to reverse proxy to
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub proxy: String,
+    /// Plain text to serve
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    /// Fields this crate does not model, kept so writing the config back
+    /// does not drop them
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Single entry that differs between two [`ServeConfig`]s. `None` means the
+/// entry is absent on that side.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServeConfigChange {
+    Tcp {
+        port: u16,
+        before: Option<TcpPortHandler>,
+        after: Option<TcpPortHandler>,
+    },
+    Web {
+        host_port: String,
+        mount: String,
+        before: Option<HttpHandler>,
+        after: Option<HttpHandler>,
+    },
+    AllowFunnel {
+        host_port: String,
+        before: Option<bool>,
+        after: Option<bool>,
+    },
+}
+
+impl ServeConfig {
+    /// Merge the entries from `other` into this config. Entries in `other`
+    /// replace entries for the same port, `host:port`, or mount point, and
+    /// everything else in this config is left untouched. The ETag of this
+    /// config is kept so the merged result can be written back safely.
+    pub fn merge(&mut self, other: &ServeConfig) {
+        for (port, handler) in &other.tcp {
+            self.tcp.insert(*port, handler.clone());
+        }
+
+        for (host_port, web) in &other.web {
+            let handlers = &mut self.web.entry(host_port.clone()).or_default().handlers;
+            for (mount, handler) in &web.handlers {
+                handlers.insert(mount.clone(), handler.clone());
+            }
+        }
+
+        for (host_port, allow) in &other.allow_funnel {
+            self.allow_funnel.insert(host_port.clone(), *allow);
+        }
+    }
+
+    /// List the entries that change going from this config to `other`.
+    pub fn diff(&self, other: &ServeConfig) -> Vec<ServeConfigChange> {
+        let mut changes = Vec::new();
+
+        for (port, before, after) in diff_maps(&self.tcp, &other.tcp) {
+            changes.push(ServeConfigChange::Tcp {
+                port: *port,
+                before: before.cloned(),
+                after: after.cloned(),
+            });
+        }
+
+        let empty = WebServerConfig::default();
+        for (host_port, before, after) in diff_maps(&self.web, &other.web) {
+            let before = before.unwrap_or(&empty);
+            let after = after.unwrap_or(&empty);
+            for (mount, before, after) in diff_maps(&before.handlers, &after.handlers) {
+                changes.push(ServeConfigChange::Web {
+                    host_port: host_port.clone(),
+                    mount: mount.clone(),
+                    before: before.cloned(),
+                    after: after.cloned(),
+                });
+            }
+        }
+
+        for (host_port, before, after) in diff_maps(&self.allow_funnel, &other.allow_funnel) {
+            changes.push(ServeConfigChange::AllowFunnel {
+                host_port: host_port.clone(),
+                before: before.copied(),
+                after: after.copied(),
+            });
+        }
+
+        changes
+    }
+}
+
+impl ServeConfig {
+    /// Check the config the way `tailscale serve` does before it is written,
+    /// so mistakes are reported with an explanation instead of tailscaled's
+    /// terse rejection. Web servers must be keyed by `host:port`, mount
+    /// points must be normalized as by [`MountPoint`], and each handler must
+    /// set exactly one of a path, a proxy, or text. Paths must be absolute,
+    /// and proxies must be a port, a `host:port`, or an `http`, `https`, or
+    /// `https+insecure` URL.
+    pub fn validate(&self) -> Result<(), crate::Error> {
+        let invalid = |message: String| Err(crate::Error::InvalidServeConfig(message));
+
+        for (host_port, web) in &self.web {
+            let port = host_port
+                .rsplit_once(':')
+                .filter(|(host, _)| !host.is_empty())
+                .and_then(|(_, port)| port.parse::<u16>().ok());
+            if port.is_none_or(|port| port == 0) {
+                return invalid(format!(
+                    "web server {host_port:?} is not keyed by host:port"
+                ));
+            }
+
+            for (mount, handler) in &web.handlers {
+                let normalized: MountPoint = mount.parse()?;
+                if normalized.as_str() != mount {
+                    return invalid(format!(
+                        "mount point {mount:?} on {host_port} is not normalized, use \"{normalized}\""
+                    ));
+                }
+
+                let location = format!("{host_port}{mount}");
+                if let Err(message) = handler.validate() {
+                    return invalid(format!("handler for {location} {message}"));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl HttpHandler {
+    fn validate(&self) -> Result<(), String> {
+        let set: Vec<&str> = [
+            ("path", &self.path),
+            ("proxy", &self.proxy),
+            ("text", &self.text),
+        ]
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, _)| name)
+        .collect();
+        match set.as_slice() {
+            // Handler kinds this crate does not model are left to tailscaled
+            [] if !self.other.is_empty() => {}
+            [] => return Err("sets none of path, proxy, or text".to_string()),
+            [_] => {}
+            set => return Err(format!("sets more than one of {}", set.join(", "))),
+        }
+
+        if !self.path.is_empty() && !std::path::Path::new(&self.path).is_absolute() {
+            return Err(format!("serves the relative path {:?}", self.path));
+        }
+
+        if !self.proxy.is_empty() && !is_valid_proxy_target(&self.proxy) {
+            return Err(format!("proxies to the invalid target {:?}", self.proxy));
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether tailscaled can proxy to the target. Like the CLI, a bare port
+/// means 127.0.0.1 and a `host:port` without a scheme means `http`.
+fn is_valid_proxy_target(target: &str) -> bool {
+    if target.bytes().all(|b| b.is_ascii_digit()) {
+        return target.parse::<u16>().is_ok_and(|port| port != 0);
+    }
+
+    let (scheme, rest) = target.split_once("://").unwrap_or(("http", target));
+    if !matches!(scheme, "http" | "https" | "https+insecure") {
+        return false;
+    }
+
+    let url = format!("http://{rest}");
+    let Ok(uri) = url.parse::<http::Uri>() else {
+        return false;
+    };
+    // `Uri::port` is `None` for a port that is not a number, so check the
+    // authority itself
+    let port = uri
+        .authority()
+        .and_then(|authority| authority.as_str().rsplit_once(':'))
+        .map(|(_, port)| port)
+        .filter(|port| !port.contains(']'));
+
+    uri.host().is_some_and(|host| !host.is_empty())
+        && port.is_none_or(|port| port.parse::<u16>().is_ok_and(|port| port != 0))
+}
+
+fn diff_maps<'a, K: Ord, V: PartialEq>(
+    before: &'a BTreeMap<K, V>,
+    after: &'a BTreeMap<K, V>,
+) -> Vec<(&'a K, Option<&'a V>, Option<&'a V>)> {
+    let mut keys: Vec<&K> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let (b, a) = (before.get(key), after.get(key));
+            (b != a).then_some((key, b, a))
+        })
+        .collect()
+}
+
+/// Preferences of the local node, as set with `tailscale up` and
+/// `tailscale set`. The default matches tailscaled's `ipn.NewPrefs()`, so
+/// fields missing from a response and prefs built from scratch keep
+/// tailscaled's defaults instead of being zeroed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Prefs {
+    #[serde(rename = "ControlURL")]
+    pub control_url: String,
+    /// Whether to accept subnet routes advertised by other nodes
+    pub route_all: bool,
+    /// Stable ID of the exit node in use
+    #[serde(rename = "ExitNodeID")]
+    pub exit_node_id: String,
+    /// Tailscale IP of the exit node in use, when set by IP
+    #[serde(rename = "ExitNodeIP")]
+    pub exit_node_ip: String,
+    /// Whether the local network stays reachable while using an exit node
+    #[serde(rename = "ExitNodeAllowLANAccess")]
+    pub exit_node_allow_lan_access: bool,
+    /// Whether to use the tailnet's DNS settings
+    #[serde(rename = "CorpDNS")]
+    pub corp_dns: bool,
+    #[serde(rename = "RunSSH")]
+    pub run_ssh: bool,
+    pub run_web_client: bool,
+    pub want_running: bool,
+    pub logged_out: bool,
+    /// Whether incoming connections are blocked
+    pub shields_up: bool,
+    #[serde(deserialize_with = "deserialize_default_from_null")]
+    pub advertise_tags: Vec<String>,
+    pub hostname: String,
+    /// Subnet routes advertised by the node, including `0.0.0.0/0` and `::/0`
+    /// when it offers to be an exit node
+    #[serde(deserialize_with = "deserialize_default_from_null")]
+    pub advertise_routes: Vec<String>,
+    #[serde(rename = "NoSNAT")]
+    pub no_snat: bool,
+    /// How tailscaled manages firewall rules on Linux: 0 for off, 1 to add
+    /// rules without diverting traffic to them, and 2 for on
+    pub netfilter_mode: i64,
+    pub operator_user: String,
+    pub profile_name: String,
+    pub auto_update: AutoUpdatePrefs,
+    /// Fields this crate does not model. These are kept so prefs can be
+    /// compared and written back without dropping them.
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// Netfilter mode that lets tailscaled manage its firewall rules
+const NETFILTER_ON: i64 = 2;
+
+impl Default for Prefs {
+    fn default() -> Self {
+        Self {
+            // Empty means the default control server
+            control_url: String::new(),
+            route_all: false,
+            exit_node_id: String::new(),
+            exit_node_ip: String::new(),
+            exit_node_allow_lan_access: false,
+            corp_dns: true,
+            run_ssh: false,
+            run_web_client: false,
+            want_running: false,
+            logged_out: false,
+            shields_up: false,
+            advertise_tags: Vec::new(),
+            hostname: String::new(),
+            advertise_routes: Vec::new(),
+            no_snat: false,
+            netfilter_mode: NETFILTER_ON,
+            operator_user: String::new(),
+            profile_name: String::new(),
+            auto_update: AutoUpdatePrefs {
+                check: true,
+                apply: None,
+            },
+            other: BTreeMap::new(),
+        }
+    }
+}
+
+impl Prefs {
+    /// Prefs for bringing a node up with [`crate::LocalApi::start`]: the
+    /// defaults of tailscaled with the node set to run, as `tailscale up`
+    /// does.
+    pub fn recommended() -> Self {
+        Self {
+            want_running: true,
+            ..Self::default()
+        }
+    }
+}
+
+/// Prefs whose `<Field>Set` mask in Go's `ipn.MaskedPrefs` is a struct with a
+/// flag per field rather than a bool
+const STRUCT_MASKS: [&str; 1] = ["AutoUpdate"];
+
+/// Change to some of the preferences of the local node, applied with
+/// [`crate::LocalApi::edit_prefs`]. Only the fields set on the builder are
+/// changed, using tailscaled's `<Field>Set` masks.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaskedPrefs {
+    fields: BTreeMap<String, serde_json::Value>,
+}
+
+impl MaskedPrefs {
+    /// Create an empty change
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set any pref by its Go field name, such as `NoStatefulFiltering`, for
+    /// prefs this crate does not model. Values are JSON values, so other
+    /// types are converted with [`serde_json::to_value`] first, where a
+    /// failure can be handled.
+    ///
+    /// Prefs whose mask is a struct, such as `AutoUpdate`, take an object and
+    /// only the fields present in it are changed, so
+    /// `set("AutoUpdate", json!({"Check": true}))` leaves `Apply` alone. Any
+    /// other value for them changes nothing.
+    pub fn set<V: Into<serde_json::Value>>(mut self, field: &str, value: V) -> Self {
+        let value = value.into();
+        let mask = if STRUCT_MASKS.contains(&field) {
+            let fields = value
+                .as_object()
+                .into_iter()
+                .flat_map(|fields| fields.keys());
+            fields
+                .map(|key| (format!("{key}Set"), serde_json::Value::Bool(true)))
+                .collect()
+        } else {
+            serde_json::Value::Bool(true)
+        };
+        self.fields.insert(field.to_string(), value);
+        self.fields.insert(format!("{field}Set"), mask);
+        self
+    }
+
+    /// Whether no prefs are changed
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Accept subnet routes advertised by other nodes, like `--accept-routes`.
+    pub fn route_all(self, route_all: bool) -> Self {
+        self.set("RouteAll", route_all)
+    }
+
+    /// Use the exit node with the given stable ID, or stop using an exit node
+    /// with an empty ID.
+    pub fn exit_node_id<S: Into<String>>(self, id: S) -> Self {
+        self.set("ExitNodeID", id.into())
+    }
+
+    /// Switch to the exit node with the given stable ID, like `tailscale set
+    /// --exit-node`. Any exit node chosen by IP is cleared so the two do not
+    /// conflict, and LAN access is set along with it.
+    pub fn use_exit_node<S: Into<String>>(self, id: S, allow_lan_access: bool) -> Self {
+        self.exit_node_id(id)
+            .exit_node_ip(None)
+            .exit_node_allow_lan_access(allow_lan_access)
+    }
+
+    /// Use the exit node with the given Tailscale IP, or stop using an exit
+    /// node with `None`.
+    pub fn exit_node_ip(self, ip: Option<IpAddr>) -> Self {
+        self.set(
+            "ExitNodeIP",
+            ip.map(|ip| ip.to_string()).unwrap_or_default(),
+        )
+    }
+
+    /// Allow access to the local network while using an exit node.
+    pub fn exit_node_allow_lan_access(self, allow: bool) -> Self {
+        self.set("ExitNodeAllowLANAccess", allow)
+    }
+
+    /// Use the DNS settings of the tailnet, like `--accept-dns`.
+    pub fn corp_dns(self, corp_dns: bool) -> Self {
+        self.set("CorpDNS", corp_dns)
+    }
+
+    /// Run the Tailscale SSH server, like `--ssh`.
+    pub fn run_ssh(self, run_ssh: bool) -> Self {
+        self.set("RunSSH", run_ssh)
+    }
+
+    /// Run the web interface for managing the node, like `--webclient`.
+    pub fn run_web_client(self, run_web_client: bool) -> Self {
+        self.set("RunWebClient", run_web_client)
+    }
+
+    /// Connect to the tailnet, or disconnect like `tailscale down`.
+    pub fn want_running(self, want_running: bool) -> Self {
+        self.set("WantRunning", want_running)
+    }
+
+    /// Block incoming connections from the tailnet, like `--shields-up`.
+    pub fn shields_up(self, shields_up: bool) -> Self {
+        self.set("ShieldsUp", shields_up)
+    }
+
+    /// Request these ACL tags for the node, like `--advertise-tags`.
+    pub fn advertise_tags(self, tags: Vec<String>) -> Self {
+        self.set("AdvertiseTags", tags)
+    }
+
+    /// Use this hostname on the tailnet instead of the OS hostname.
+    pub fn hostname<S: Into<String>>(self, hostname: S) -> Self {
+        self.set("Hostname", hostname.into())
+    }
+
+    /// Offer these subnet routes to the tailnet, like `--advertise-routes`.
+    pub fn advertise_routes(self, routes: Vec<String>) -> Self {
+        self.set("AdvertiseRoutes", routes)
+    }
+
+    /// Keep the source address of traffic to advertised subnets, like
+    /// `--snat-subnet-routes=false`.
+    pub fn no_snat(self, no_snat: bool) -> Self {
+        self.set("NoSNAT", no_snat)
+    }
+
+    /// Let this local user operate tailscaled without root, like
+    /// `--operator`.
+    pub fn operator_user<S: Into<String>>(self, user: S) -> Self {
+        self.set("OperatorUser", user.into())
+    }
+}
+
+impl Serialize for MaskedPrefs {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.fields.serialize(serializer)
+    }
+}
+
+/// Options for [`crate::LocalApi::start`]
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct StartOptions {
+    /// Pre-authentication key to log in with
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub auth_key: String,
+    /// Preferences to replace the current ones with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub update_prefs: Option<Prefs>,
+}
 
-/// DER encoded private key for the node
-pub struct PrivateKey(pub Vec<u8>);
+impl std::fmt::Debug for StartOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let auth_key = if self.auth_key.is_empty() {
+            ""
+        } else {
+            "<redacted>"
+        };
+        f.debug_struct("StartOptions")
+            .field("auth_key", &auth_key)
+            .field("update_prefs", &self.update_prefs)
+            .finish()
+    }
+}
+
+/// Answer to a prefs check, with an empty error when the prefs are valid
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct CheckPrefsResponse {
+    #[serde(default)]
+    pub(crate) error: String,
+}
+
+/// OIDC token issued by the control server for the local node
+#[derive(Deserialize, Debug)]
+pub(crate) struct IdTokenResponse {
+    pub(crate) id_token: String,
+}
+
+/// Answer to a config reload request
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub(crate) struct ReloadConfigResponse {
+    #[serde(default)]
+    pub(crate) reloaded: bool,
+    #[serde(default)]
+    pub(crate) err: String,
+}
+
+/// Automatic update preferences of the local node
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct AutoUpdatePrefs {
+    /// Whether to check for updates
+    pub check: bool,
+    /// Whether to install updates, or `None` when never chosen
+    pub apply: Option<bool>,
+}
+
+/// Ports that funnel can be enabled on
+pub const FUNNEL_PORTS: [u16; 3] = [443, 8443, 10000];
+
+/// Answer from tailscaled about whether a feature can be used, and if not,
+/// what the user needs to do to enable it
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct QueryFeatureResponse {
+    /// Whether the feature is ready to use
+    #[serde(default)]
+    pub complete: bool,
+    /// Instructions for enabling the feature
+    #[serde(default)]
+    pub text: String,
+    /// Page where the feature can be enabled
+    #[serde(rename = "URL", default)]
+    pub url: String,
+    /// Whether tailscaled is waiting for the feature to be enabled
+    #[serde(default)]
+    pub should_wait: bool,
+}
+
+/// Report of the prerequisites for funnel, created with
+/// [`crate::LocalApi::funnel_readiness`]
+#[derive(Debug, Clone)]
+pub struct FunnelReadiness {
+    /// Whether HTTPS certificates are enabled for the tailnet
+    pub https_enabled: bool,
+    /// Whether the node has the funnel node attribute
+    pub funnel_attribute: bool,
+    /// Answer from tailscaled about enabling funnel
+    pub feature: QueryFeatureResponse,
+    /// Entries in [`ServeConfig::allow_funnel`] that cannot work, either
+    /// because the port is not one of [`FUNNEL_PORTS`] or nothing is served
+    /// there
+    pub invalid_host_ports: Vec<String>,
+}
+
+impl FunnelReadiness {
+    /// Whether everything needed to use funnel is in place
+    pub fn is_ready(&self) -> bool {
+        self.https_enabled
+            && self.funnel_attribute
+            && self.feature.complete
+            && self.invalid_host_ports.is_empty()
+    }
+}
+
+impl ServeConfig {
+    /// Entries in [`ServeConfig::allow_funnel`] that are enabled but use a
+    /// port funnel does not support or have nothing served behind them
+    pub fn invalid_funnel_host_ports(&self) -> Vec<String> {
+        self.allow_funnel
+            .iter()
+            .filter(|&(_, &allowed)| allowed)
+            .filter(|&(host_port, _)| {
+                let port = host_port
+                    .rsplit_once(':')
+                    .and_then(|(_, port)| port.parse::<u16>().ok());
+                let served = port.is_some_and(|port| self.tcp.contains_key(&port))
+                    || self.web.contains_key(host_port);
+                !port.is_some_and(|port| FUNNEL_PORTS.contains(&port)) || !served
+            })
+            .map(|(host_port, _)| host_port.clone())
+            .collect()
+    }
+}
+
+/// Notification from the IPN bus. Only the fields relevant to the
+/// notification are set.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct Notify {
+    pub version: Option<String>,
+    #[serde(rename = "SessionID")]
+    pub session_id: Option<String>,
+    pub err_message: Option<String>,
+    pub login_finished: Option<serde_json::Value>,
+    #[serde(default, deserialize_with = "deserialize_backend_state_number")]
+    pub state: Option<BackendState>,
+    pub prefs: Option<serde_json::Value>,
+    pub net_map: Option<serde_json::Value>,
+    pub engine: Option<EngineStatus>,
+    #[serde(rename = "BrowseToURL")]
+    pub browse_to_url: Option<String>,
+    pub health: Option<serde_json::Value>,
+    /// Set when Taildrop files are waiting to be picked up
+    pub files_waiting: Option<serde_json::Value>,
+    /// Taildrop files being sent by tailscaled, with their progress
+    pub outgoing_files: Option<Vec<OutgoingFile>>,
+}
+
+/// Taildrop file being sent to a peer, as reported on the IPN bus
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct OutgoingFile {
+    #[serde(rename = "ID")]
+    pub id: String,
+    /// Stable ID of the receiving node
+    #[serde(rename = "PeerID")]
+    pub peer_id: String,
+    pub name: String,
+    pub started: DateTime<Utc>,
+    /// Size of the file in bytes, or 0 when it is not known
+    #[serde(default)]
+    pub declared_size: i64,
+    /// Bytes sent so far
+    #[serde(default)]
+    pub sent: i64,
+    #[serde(default)]
+    pub finished: bool,
+    #[serde(default)]
+    pub succeeded: bool,
+}
+
+/// Status of the wireguard engine
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct EngineStatus {
+    /// Total bytes received
+    #[serde(rename = "RBytes")]
+    pub rx_bytes: i64,
+    /// Total bytes sent
+    #[serde(rename = "WBytes")]
+    pub tx_bytes: i64,
+    /// Number of peers with a recent handshake
+    pub num_live: i64,
+    /// Number of live DERP connections
+    #[serde(rename = "LiveDERPs")]
+    pub live_derps: i64,
+    /// Peers with a recent handshake, keyed by node public key
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub live_peers: HashMap<String, PeerStatusLite>,
+}
+
+/// Traffic and handshake details of a live peer
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct PeerStatusLite {
+    pub rx_bytes: i64,
+    pub tx_bytes: i64,
+    pub last_handshake: DateTime<Utc>,
+    pub node_key: String,
+}
+
+/// Full picture of the local node, from [`crate::LocalApi::snapshot`]
+#[derive(Debug)]
+pub struct Snapshot {
+    pub status: Status,
+    pub prefs: Prefs,
+    pub health: HealthState,
+    pub derp_map: DerpMap,
+}
+
+/// Health of tailscaled, as sent on the IPN bus
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct HealthState {
+    /// Current warnings, keyed by warning code
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub warnings: HashMap<String, HealthWarning>,
+}
+
+/// Problem reported by tailscaled's health checks
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct HealthWarning {
+    #[serde(default)]
+    pub warnable_code: String,
+    /// `"high"`, `"medium"` or `"low"`
+    #[serde(default)]
+    pub severity: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub broken_since: Option<DateTime<Utc>>,
+    /// Whether the problem stops traffic from flowing
+    #[serde(default)]
+    pub impacts_connectivity: bool,
+}
+
+/// Layer a ping is sent at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PingType {
+    /// WireGuard discovery message, which shows whether a path to the peer
+    /// exists without going through the tunnel
+    #[default]
+    Disco,
+    /// Tailscale message protocol ping through the tunnel, answered by
+    /// tailscaled on the peer
+    Tsmp,
+    /// ICMP echo through the tunnel, answered by the peer's network stack
+    Icmp,
+    /// Request to the peer's peer API, which shows that tailscaled on the
+    /// peer is serving
+    PeerApi,
+}
+
+impl PingType {
+    /// Name of the ping type used by tailscaled
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Disco => "disco",
+            Self::Tsmp => "TSMP",
+            Self::Icmp => "ICMP",
+            Self::PeerApi => "peerapi",
+        }
+    }
+}
+
+/// Go runtime profile of tailscaled, fetched with
+/// [`crate::Debugging::pprof`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PprofProfile {
+    /// Live heap allocations
+    Heap,
+    /// All past allocations
+    Allocs,
+    /// CPU usage over a number of seconds
+    Cpu,
+    /// Goroutines blocked on synchronization
+    Block,
+    /// Holders of contended mutexes
+    Mutex,
+    /// Stacks of all goroutines
+    Goroutine,
+}
+
+impl PprofProfile {
+    /// Name of the profile used by tailscaled
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Heap => "heap",
+            Self::Allocs => "allocs",
+            Self::Cpu => "profile",
+            Self::Block => "block",
+            Self::Mutex => "mutex",
+            Self::Goroutine => "goroutine",
+        }
+    }
+}
+
+/// Transport protocol of the flow being looked up with
+/// [`crate::LocalApi::whois_with_proto`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhoisProto {
+    Tcp,
+    Udp,
+}
+
+impl WhoisProto {
+    /// Name of the protocol used by tailscaled
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Udp => "udp",
+        }
+    }
+}
+
+/// Result of pinging a peer
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct PingResult {
+    /// Address that was pinged
+    #[serde(rename = "IP")]
+    pub ip: String,
+    /// Tailscale IP of the node that answered
+    #[serde(rename = "NodeIP")]
+    pub node_ip: String,
+    pub node_name: String,
+    /// Why the ping failed, empty on success
+    pub err: String,
+    pub latency_seconds: f64,
+    /// Endpoint of a direct connection, empty when relayed
+    pub endpoint: String,
+    /// DERP region the ping was relayed through, zero when direct
+    #[serde(rename = "DERPRegionID")]
+    pub derp_region_id: i32,
+    #[serde(rename = "DERPRegionCode")]
+    pub derp_region_code: String,
+    /// Peer relay the ping went through, empty when not relayed by a peer
+    pub peer_relay: String,
+    /// Port of the peer's PeerAPI, set by TSMP pings and zero otherwise
+    #[serde(rename = "PeerAPIPort")]
+    pub peer_api_port: u16,
+    /// URL of the peer's PeerAPI, set by PeerAPI pings
+    #[serde(rename = "PeerAPIURL")]
+    pub peer_api_url: String,
+    /// Whether the address belongs to the local node
+    #[serde(rename = "IsLocalIP")]
+    pub is_local_ip: bool,
+}
+
+impl PingResult {
+    /// Round trip time, or `None` if the ping failed
+    pub fn latency(&self) -> Option<std::time::Duration> {
+        if !self.err.is_empty() {
+            return None;
+        }
+
+        std::time::Duration::try_from_secs_f64(self.latency_seconds).ok()
+    }
+
+    /// Whether the ping went over a direct connection rather than DERP
+    pub fn is_direct(&self) -> bool {
+        !self.endpoint.is_empty()
+    }
+}
+
+/// DERP relay regions known to the local node
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct DerpMap {
+    /// Regions keyed by region ID
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub regions: BTreeMap<i32, DerpRegion>,
+    /// Whether the control server asked to leave out Tailscale's own regions
+    #[serde(default)]
+    pub omit_default_regions: bool,
+}
+
+/// Geographic region with one or more DERP relays
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct DerpRegion {
+    #[serde(rename = "RegionID")]
+    pub region_id: i32,
+    /// Short name such as `nyc`
+    pub region_code: String,
+    /// Long name such as `New York City`
+    pub region_name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Whether clients should avoid picking this region as their home
+    pub avoid: bool,
+    #[serde(deserialize_with = "deserialize_default_from_null")]
+    pub nodes: Vec<DerpNode>,
+}
+
+/// DERP relay server in a [`DerpRegion`]
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct DerpNode {
+    /// Unique name of the node, such as `1b`
+    pub name: String,
+    #[serde(rename = "RegionID")]
+    pub region_id: i32,
+    pub host_name: String,
+    /// Name expected in the node's TLS certificate, when different from the
+    /// hostname
+    pub cert_name: String,
+    /// IPv4 address to use instead of resolving the hostname, `none` to
+    /// disable IPv4
+    #[serde(rename = "IPv4")]
+    pub ipv4: String,
+    /// IPv6 address to use instead of resolving the hostname, `none` to
+    /// disable IPv6
+    #[serde(rename = "IPv6")]
+    pub ipv6: String,
+    /// STUN port, where zero means the default of 3478 and -1 disables STUN
+    #[serde(rename = "STUNPort")]
+    pub stun_port: i32,
+    /// Whether the node only serves STUN and not DERP
+    #[serde(rename = "STUNOnly")]
+    pub stun_only: bool,
+    /// DERP port, where zero means the default of 443
+    #[serde(rename = "DERPPort")]
+    pub derp_port: i32,
+}
+
+/// Exit node recommended by tailscaled
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct ExitNodeSuggestion {
+    /// Stable node ID, usable with [`MaskedPrefs::exit_node_id`]
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub name: String,
+    /// Where the exit node is, for Mullvad and other located exit nodes
+    #[serde(default)]
+    pub location: Option<Location>,
+}
+
+/// Geographic location of a node
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Location {
+    pub country: String,
+    pub country_code: String,
+    pub city: String,
+    pub city_code: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Preference among nodes in the same location, higher is better
+    pub priority: i64,
+}
+
+/// Answer to a DNS query made through tailscaled
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct DnsQueryResponse {
+    /// Raw DNS response message
+    #[serde(deserialize_with = "deserialize_base64")]
+    pub bytes: Vec<u8>,
+    /// Resolvers the query was forwarded to
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub resolvers: Vec<DnsResolver>,
+}
+
+impl DnsQueryResponse {
+    /// IPv4 and IPv6 addresses in the answer section, without needing a DNS
+    /// library. Parsing stops at the first malformed record, keeping the
+    /// addresses found before it.
+    pub fn addresses(&self) -> Vec<IpAddr> {
+        let mut addresses = Vec::new();
+        let _ = parse_dns_addresses(&self.bytes, &mut addresses);
+        addresses
+    }
+}
+
+/// Collect the A and AAAA records of a DNS message's answer section.
+/// Returns `None` when the message is cut short.
+fn parse_dns_addresses(message: &[u8], addresses: &mut Vec<IpAddr>) -> Option<()> {
+    const TYPE_A: u16 = 1;
+    const TYPE_AAAA: u16 = 28;
+
+    let read_u16 = |offset: usize| -> Option<u16> {
+        let bytes = message.get(offset..offset + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    // Skip a possibly compressed name, returning the offset after it
+    let skip_name = |mut offset: usize| -> Option<usize> {
+        loop {
+            let len = *message.get(offset)?;
+            match len {
+                0 => return Some(offset + 1),
+                len if len & 0xc0 == 0xc0 => return Some(offset + 2),
+                len => offset += 1 + usize::from(len),
+            }
+        }
+    };
+
+    let questions = read_u16(4)?;
+    let answers = read_u16(6)?;
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = skip_name(offset)? + 4;
+    }
+    for _ in 0..answers {
+        offset = skip_name(offset)?;
+        let record_type = read_u16(offset)?;
+        let len = usize::from(read_u16(offset + 8)?);
+        offset += 10;
+        let data = message.get(offset..offset + len)?;
+        offset += len;
+
+        match (
+            record_type,
+            <[u8; 4]>::try_from(data),
+            <[u8; 16]>::try_from(data),
+        ) {
+            (TYPE_A, Ok(octets), _) => addresses.push(Ipv4Addr::from(octets).into()),
+            (TYPE_AAAA, _, Ok(octets)) => addresses.push(Ipv6Addr::from(octets).into()),
+            _ => {}
+        }
+    }
+
+    Some(())
+}
+
+/// DNS configuration tailscaled has applied to the OS
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "PascalCase")]
+pub struct DnsOsConfig {
+    /// Resolvers the OS sends queries to
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub nameservers: Vec<String>,
+    /// Domains appended to unqualified names
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub search_domains: Vec<String>,
+    /// Domains routed to tailscaled's resolver when the OS supports split
+    /// DNS
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub match_domains: Vec<String>,
+}
+
+/// Upstream DNS resolver used by tailscaled
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct DnsResolver {
+    /// Address of the resolver, such as `8.8.8.8:53` or a DoH URL
+    pub addr: String,
+    /// Addresses used to reach a resolver given by hostname
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub bootstrap_resolution: Vec<IpAddr>,
+    #[serde(default)]
+    pub use_with_exit_node: bool,
+}
+
+/// DNS configuration of the tailnet from the netmap, including the split
+/// DNS routes that send some domains to their own resolvers
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct DnsConfig {
+    /// Resolvers for names that no route matches
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub resolvers: Vec<DnsResolver>,
+    /// Resolvers keyed by domain suffix. An empty list means tailscaled
+    /// answers names under the suffix itself, as for MagicDNS.
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub routes: BTreeMap<String, Vec<DnsResolver>>,
+    /// Resolvers used when the OS has none of its own
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub fallback_resolvers: Vec<DnsResolver>,
+    /// Search domains
+    #[serde(default, deserialize_with = "deserialize_default_from_null")]
+    pub domains: Vec<String>,
+    /// Whether MagicDNS is enabled
+    #[serde(default)]
+    pub proxied: bool,
+}
+
+impl DnsConfig {
+    /// Read the DNS configuration from a netmap sent on the IPN bus, such as
+    /// from [`crate::NotifyFilter::netmap_only`]
+    pub fn from_netmap(netmap: &serde_json::Value) -> Result<Self, crate::Error> {
+        match netmap.get("DNS") {
+            Some(dns) if !dns.is_null() => Ok(Self::deserialize(dns)?),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Split DNS route for a name, as the suffix that matched and its
+    /// resolvers. The longest matching suffix wins, as in tailscaled. `None`
+    /// means no route matches and [`DnsConfig::resolvers`] answer the name.
+    pub fn resolvers_for(&self, name: &str) -> Option<(&str, &[DnsResolver])> {
+        let name = normalize_dns_suffix(name);
+        self.routes
+            .iter()
+            .filter(|(suffix, _)| {
+                let suffix = normalize_dns_suffix(suffix);
+                name == suffix
+                    || name
+                        .strip_suffix(&suffix)
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            })
+            .max_by_key(|(suffix, _)| normalize_dns_suffix(suffix).len())
+            .map(|(suffix, resolvers)| (suffix.as_str(), resolvers.as_slice()))
+    }
+}
+
+fn normalize_dns_suffix(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn deserialize_base64<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use base64::Engine;
+
+    let encoded = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Taildrop file waiting to be picked up
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct WaitingFile {
+    pub name: String,
+    pub size: i64,
+}
+
+/// Value of a custom device posture attribute
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum PostureAttributeValue {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    /// Remove the attribute from the device
+    Delete,
+}
+
+/// Normalized DNS name, such as `host.tailnet.ts.net`. Names are lowercased,
+/// a trailing dot is removed, and each label is checked to be a valid
+/// hostname label, except that underscores are allowed for service labels
+/// such as `_acme-challenge` used in DNS queries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DnsName(String);
+
+impl DnsName {
+    /// The normalized name
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Key for this name and port as used in [`ServeConfig::web`] and
+    /// [`ServeConfig::allow_funnel`].
+    pub fn host_port(&self, port: u16) -> String {
+        format!("{}:{port}", self.0)
+    }
+}
+
+impl std::str::FromStr for DnsName {
+    type Err = crate::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::Error::InvalidDnsName(name.to_string());
+
+        let normalized = name.strip_suffix('.').unwrap_or(name).to_ascii_lowercase();
+        if normalized.is_empty() || normalized.len() > 253 {
+            return Err(invalid());
+        }
+
+        let valid_labels = normalized.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+        });
+        if !valid_labels {
+            return Err(invalid());
+        }
+
+        Ok(Self(normalized))
+    }
+}
+
+impl TryFrom<&str> for DnsName {
+    type Error = crate::Error;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        name.parse()
+    }
+}
+
+impl std::fmt::Display for DnsName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for DnsName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Normalized mount point of an [`HttpHandler`], such as `/` or `/api/`.
+/// Like `tailscale serve`, a leading slash is added, repeated slashes and
+/// `.` and `..` segments are removed, and a trailing slash is kept. An empty
+/// mount point is `/`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MountPoint(String);
+
+impl MountPoint {
+    /// The normalized mount point
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for MountPoint {
+    type Err = crate::Error;
+
+    fn from_str(mount: &str) -> Result<Self, Self::Err> {
+        if mount.chars().any(char::is_whitespace) {
+            return Err(crate::Error::InvalidMountPoint(mount.to_string()));
+        }
+
+        let mut segments: Vec<&str> = Vec::new();
+        for segment in mount.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                segment => segments.push(segment),
+            }
+        }
+
+        let mut normalized = format!("/{}", segments.join("/"));
+        if mount.ends_with('/') && normalized != "/" {
+            normalized.push('/');
+        }
+
+        Ok(Self(normalized))
+    }
+}
+
+impl TryFrom<&str> for MountPoint {
+    type Error = crate::Error;
+
+    fn try_from(mount: &str) -> Result<Self, Self::Error> {
+        mount.parse()
+    }
+}
+
+impl std::fmt::Display for MountPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for MountPoint {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// DER encoded X.509 certificate for the node. This can either be the leaf
+/// certificate or part of the certificate chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate(pub Vec<u8>);
+
+/// DER encoded private key for the node. The key bytes are left out of the
+/// `Debug` output, and with the `zeroize` feature they are wiped when the key
+/// is dropped. The bytes are private, so code using the key compiles the same
+/// with or without the feature.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PrivateKey(Vec<u8>);
+
+impl PrivateKey {
+    /// Wrap DER encoded key bytes
+    pub fn new(der: Vec<u8>) -> Self {
+        Self(der)
+    }
+
+    /// The DER encoded key
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Take the DER encoded key out. The returned bytes are not wiped when
+    /// dropped, even with the `zeroize` feature.
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+impl AsRef<[u8]> for PrivateKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PrivateKey").field(&"<redacted>").finish()
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+#[cfg(feature = "rustls-021")]
+impl From<Certificate> for rustls_021::Certificate {
+    fn from(certificate: Certificate) -> Self {
+        Self(certificate.0)
+    }
+}
+
+#[cfg(feature = "rustls-021")]
+impl From<PrivateKey> for rustls_021::PrivateKey {
+    fn from(key: PrivateKey) -> Self {
+        Self(key.into_bytes())
+    }
+}
+
+#[cfg(feature = "pki-types")]
+impl From<Certificate> for rustls_pki_types::CertificateDer<'static> {
+    fn from(certificate: Certificate) -> Self {
+        Self::from(certificate.0)
+    }
+}
+
+/// The key format is detected from the DER encoding, since PKCS#1, SEC1, and
+/// PKCS#8 keys are all returned as a [`PrivateKey`].
+#[cfg(feature = "pki-types")]
+impl TryFrom<PrivateKey> for rustls_pki_types::PrivateKeyDer<'static> {
+    type Error = crate::Error;
+
+    fn try_from(key: PrivateKey) -> Result<Self, Self::Error> {
+        Self::try_from(key.into_bytes()).map_err(|_| crate::Error::UnknownCertificateOrKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+
+    use super::*;
+
+    #[test]
+    fn split_dns_longest_suffix_wins() {
+        let netmap = serde_json::json!({
+            "DNS": {
+                "Resolvers": [{"Addr": "1.1.1.1"}],
+                "Routes": {
+                    "example.com.": [{"Addr": "10.0.0.53"}],
+                    "corp.example.com": [{"Addr": "10.1.0.53"}],
+                    "ts.net": [],
+                },
+            },
+        });
+        let config = DnsConfig::from_netmap(&netmap).unwrap();
+
+        let (suffix, resolvers) = config.resolvers_for("Host.Corp.Example.com.").unwrap();
+        assert_eq!(suffix, "corp.example.com");
+        assert_eq!(resolvers[0].addr, "10.1.0.53");
+        let (suffix, _) = config.resolvers_for("www.example.com").unwrap();
+        assert_eq!(suffix, "example.com.");
+        let (_, resolvers) = config.resolvers_for("host.ts.net").unwrap();
+        assert!(resolvers.is_empty());
+        assert!(config.resolvers_for("notexample.com").is_none());
+        assert!(config.resolvers_for("example.org").is_none());
+    }
+
+    #[test]
+    fn serve_config_keeps_unknown_nested_fields() {
+        let json = serde_json::json!({
+            "TCP": {"443": {"HTTPS": true, "ProxyProtocol": 2}},
+            "Web": {
+                "host.ts.net:443": {
+                    "Handlers": {
+                        "/": {"Proxy": "http://127.0.0.1:3000", "AcceptAppCaps": ["example.com/cap"]},
+                    },
+                },
+            },
+        });
+        let config: ServeConfig = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&config).unwrap(), json);
+
+        let mut changed = config.clone();
+        let handler = changed
+            .web
+            .get_mut("host.ts.net:443")
+            .and_then(|web| web.handlers.get_mut("/"))
+            .unwrap();
+        handler.other.remove("AcceptAppCaps");
+        assert_eq!(config.diff(&changed).len(), 1);
+    }
+
+    fn mount(mount: &str) -> String {
+        mount.parse::<MountPoint>().unwrap().to_string()
+    }
+
+    fn config_with(mount: &str, handler: HttpHandler) -> ServeConfig {
+        let mut web = WebServerConfig::default();
+        web.handlers.insert(mount.to_string(), handler);
+        let mut config = ServeConfig::default();
+        config.web.insert("host.ts.net:443".to_string(), web);
+        config
+    }
+
+    fn proxy(target: &str) -> HttpHandler {
+        HttpHandler {
+            proxy: target.to_string(),
+            ..HttpHandler::default()
+        }
+    }
+
+    #[test]
+    fn private_keys_hide_their_bytes() {
+        let key = PrivateKey::new(vec![1, 2, 3]);
+        assert_eq!(format!("{key:?}"), r#"PrivateKey("<redacted>")"#);
+        assert_eq!(key.as_bytes(), [1, 2, 3]);
+        assert_eq!(key.into_bytes(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn struct_prefs_get_a_mask_per_field() {
+        let prefs = MaskedPrefs::new()
+            .set(
+                "AutoUpdate",
+                serde_json::json!({"Check": true, "Apply": false}),
+            )
+            .set("NoStatefulFiltering", true);
+        assert_eq!(
+            serde_json::to_value(prefs).unwrap(),
+            serde_json::json!({
+                "AutoUpdate": {"Check": true, "Apply": false},
+                "AutoUpdateSet": {"CheckSet": true, "ApplySet": true},
+                "NoStatefulFiltering": true,
+                "NoStatefulFilteringSet": true,
+            })
+        );
+
+        let check_only = MaskedPrefs::new().set("AutoUpdate", serde_json::json!({"Check": false}));
+        assert_eq!(
+            serde_json::to_value(check_only).unwrap()["AutoUpdateSet"],
+            serde_json::json!({"CheckSet": true})
+        );
+
+        let invalid = MaskedPrefs::new().set("AutoUpdate", true);
+        assert_eq!(
+            serde_json::to_value(invalid).unwrap()["AutoUpdateSet"],
+            serde_json::json!({})
+        );
+    }
+
+    #[test]
+    fn use_exit_node_sets_all_masks() {
+        let prefs = MaskedPrefs::new().use_exit_node("nABC123CNTRL", true);
+        assert_eq!(
+            serde_json::to_value(prefs).unwrap(),
+            serde_json::json!({
+                "ExitNodeID": "nABC123CNTRL",
+                "ExitNodeIDSet": true,
+                "ExitNodeIP": "",
+                "ExitNodeIPSet": true,
+                "ExitNodeAllowLANAccess": true,
+                "ExitNodeAllowLANAccessSet": true,
+            })
+        );
+    }
+
+    #[test]
+    fn dns_names_are_normalized() {
+        let name: DnsName = "Host.Tailnet.TS.net.".parse().unwrap();
+        assert_eq!(name.as_str(), "host.tailnet.ts.net");
+        assert_eq!(name.host_port(443), "host.tailnet.ts.net:443");
+        assert!("_acme-challenge.host.tailnet.ts.net"
+            .parse::<DnsName>()
+            .is_ok());
+        for name in ["", ".", "a..b", "-a.b", "a-.b", "a b", "a/b"] {
+            assert!(name.parse::<DnsName>().is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn mount_points_are_normalized_like_the_cli() {
+        assert_eq!(mount(""), "/");
+        assert_eq!(mount("/"), "/");
+        assert_eq!(mount("api"), "/api");
+        assert_eq!(mount("/api/"), "/api/");
+        assert_eq!(mount("//a//b/"), "/a/b/");
+        assert_eq!(mount("/a/./b/../c"), "/a/c");
+        assert_eq!(mount("/../.."), "/");
+        assert!(matches!(
+            "/a b".parse::<MountPoint>(),
+            Err(crate::Error::InvalidMountPoint(_))
+        ));
+    }
+
+    #[test]
+    fn mount_points_must_be_normalized() {
+        assert!(config_with("/api/", proxy("3000")).validate().is_ok());
+        let error = config_with("api", proxy("3000")).validate().unwrap_err();
+        assert!(error.to_string().contains(r#"use "/api""#), "{error}");
+    }
+
+    #[test]
+    fn web_servers_are_keyed_by_host_and_port() {
+        for key in ["host.ts.net", "host.ts.net:0", ":443", "host.ts.net:https"] {
+            let mut config = config_with("/", proxy("3000"));
+            let web = config.web.remove("host.ts.net:443").unwrap();
+            config.web.insert(key.to_string(), web);
+            assert!(config.validate().is_err(), "{key}");
+        }
+    }
+
+    #[test]
+    fn handlers_set_exactly_one_target() {
+        let none = config_with("/", HttpHandler::default()).validate();
+        assert!(none.unwrap_err().to_string().contains("sets none"));
+
+        let both = HttpHandler {
+            path: "/srv".to_string(),
+            text: "hello".to_string(),
+            ..HttpHandler::default()
+        };
+        let both = config_with("/", both).validate();
+        assert!(both
+            .unwrap_err()
+            .to_string()
+            .contains("more than one of path, text"));
+
+        let text = HttpHandler {
+            text: "hello".to_string(),
+            ..HttpHandler::default()
+        };
+        assert!(config_with("/", text).validate().is_ok());
+    }
+
+    #[test]
+    fn served_paths_are_absolute() {
+        let relative = HttpHandler {
+            path: "srv/www".to_string(),
+            ..HttpHandler::default()
+        };
+        let error = config_with("/", relative).validate().unwrap_err();
+        assert!(error.to_string().contains("relative path"));
+
+        let absolute = HttpHandler {
+            path: "/srv/www".to_string(),
+            ..HttpHandler::default()
+        };
+        assert!(config_with("/", absolute).validate().is_ok());
+    }
+
+    #[test]
+    fn proxy_targets_match_the_cli() {
+        for target in [
+            "3000",
+            "localhost:3000",
+            "127.0.0.1:8080",
+            "http://127.0.0.1:8080",
+            "https://localhost:8443/app",
+            "https+insecure://localhost:8443",
+            "http://[::1]:3000",
+        ] {
+            assert!(
+                config_with("/", proxy(target)).validate().is_ok(),
+                "{target}"
+            );
+        }
+
+        for target in [
+            "0",
+            "70000",
+            "ftp://localhost:21",
+            "http://",
+            "http://localhost:0",
+            "localhost:port",
+        ] {
+            let error = config_with("/", proxy(target)).validate().unwrap_err();
+            assert!(error.to_string().contains("invalid target"), "{target}");
+        }
+    }
+
+    #[test]
+    fn netmap_without_dns() {
+        let config = DnsConfig::from_netmap(&serde_json::json!({"DNS": null})).unwrap();
+        assert!(config.routes.is_empty());
+    }
+
+    /// DNS response for `example.com` with the given rcode and answers, each
+    /// a record type and its data, named by a pointer to the question
+    fn dns_message(rcode: u8, answers: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut message = vec![0x12, 0x34, 0x81, 0x80 | rcode, 0, 1];
+        message.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        message.extend_from_slice(&[0, 0, 0, 0]);
+        message.extend_from_slice(b"\x07example\x03com\x00\x00\x01\x00\x01");
+        for (record_type, data) in answers {
+            message.extend_from_slice(&[0xc0, 12]);
+            message.extend_from_slice(&record_type.to_be_bytes());
+            message.extend_from_slice(&[0, 1, 0, 0, 0x0e, 0x10]);
+            message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            message.extend_from_slice(data);
+        }
+        message
+    }
+
+    fn dns_response(message: &[u8]) -> DnsQueryResponse {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(message);
+        serde_json::from_value(serde_json::json!({"Bytes": encoded, "Resolvers": null})).unwrap()
+    }
+
+    #[test]
+    fn dns_answers_are_parsed() {
+        let ipv6 = "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets();
+        let message = dns_message(
+            0,
+            &[
+                (5, b"\x03www\xc0\x0c"),
+                (1, &[93, 184, 216, 34]),
+                (28, &ipv6),
+            ],
+        );
+        let response = dns_response(&message);
+
+        assert!(response.resolvers.is_empty());
+        assert_eq!(
+            response.addresses(),
+            ["93.184.216.34".parse::<IpAddr>().unwrap(), ipv6.into()]
+        );
+    }
+
+    #[test]
+    fn truncated_dns_answers_keep_earlier_addresses() {
+        let message = dns_message(0, &[(1, &[10, 0, 0, 1]), (1, &[10, 0, 0, 2])]);
+        let response = dns_response(&message[..message.len() - 2]);
+        assert_eq!(response.addresses(), [IpAddr::from([10, 0, 0, 1])]);
+
+        assert!(dns_response(&message[..5]).addresses().is_empty());
+        assert!(dns_response(&[]).addresses().is_empty());
+        // A label running past the end of the message
+        assert!(
+            dns_response(&[0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 63, b'a'])
+                .addresses()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn nxdomain_has_no_addresses() {
+        let response = dns_response(&dns_message(3, &[]));
+        assert_eq!(response.bytes[3] & 0x0f, 3);
+        assert!(response.addresses().is_empty());
+    }
+
+    fn peer_json(ips: &[&str], allowed_ips: &[&str]) -> serde_json::Value {
+        serde_json::json!({
+            "ID": "n1",
+            "PublicKey": "nodekey:1",
+            "HostName": "peer",
+            "DNSName": "peer.example.ts.net.",
+            "OS": "linux",
+            "UserID": 1,
+            "TailscaleIPs": ips,
+            "AllowedIPs": allowed_ips,
+            "Addrs": null,
+            "CurAddr": "",
+            "Relay": "nyc",
+            "RxBytes": 0,
+            "TxBytes": 0,
+            "Created": "2024-01-01T00:00:00Z",
+            "LastWrite": "2024-01-01T00:00:00Z",
+            "LastSeen": "2024-01-01T00:00:00Z",
+            "LastHandshake": "2024-01-01T00:00:00Z",
+            "Online": true,
+            "ExitNode": false,
+            "ExitNodeOption": false,
+            "Active": false,
+            "PeerAPIURL": null,
+            "InNetworkMap": true,
+            "InMagicSock": true,
+            "InEngine": true,
+        })
+    }
+
+    /// Status of a node at `100.64.0.1` with one peer
+    fn status_with_peer(peer_ips: &[&str], peer_allowed_ips: &[&str]) -> Status {
+        serde_json::from_value(serde_json::json!({
+            "Version": "1.80.0",
+            "BackendState": "Running",
+            "AuthURL": "",
+            "TailscaleIPs": ["100.64.0.1", "fd7a:115c:a1e0::1"],
+            "Self": peer_json(&["100.64.0.1", "fd7a:115c:a1e0::1"], &[]),
+            "Health": null,
+            "CertDomains": null,
+            "Peer": {"nodekey:2": peer_json(peer_ips, peer_allowed_ips)},
+            "User": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn tailnet_ips_include_nodes_and_subnet_routes() {
+        let status = status_with_peer(
+            &["100.64.0.2"],
+            &["100.64.0.2/32", "192.168.1.0/24", "0.0.0.0/0", "::/0"],
+        );
+        let contains = |ip: &str| status.contains_ip(ip.parse().unwrap());
+
+        assert!(contains("100.64.0.1"));
+        assert!(contains("fd7a:115c:a1e0::1"));
+        assert!(contains("100.64.0.2"));
+        assert!(contains("192.168.1.77"));
+        assert!(contains("::ffff:100.64.0.2"));
+        // Exit node routes and other CGNAT addresses are not the tailnet
+        assert!(!contains("8.8.8.8"));
+        assert!(!contains("2001:db8::1"));
+        assert!(!contains("100.64.0.3"));
+        assert!(!contains("192.168.2.1"));
+    }
+
+    #[test]
+    fn via_routes_map_sites_into_ipv6() {
+        let address = map_4via6(7, Ipv4Addr::new(10, 1, 1, 0));
+        assert_eq!(
+            address,
+            "fd7a:115c:a1e0:b1a:0:7:a01:100"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+        );
+        assert_eq!(unmap_4via6(address), Some((7, Ipv4Addr::new(10, 1, 1, 0))));
+        assert_eq!(unmap_4via6("fd7a:115c:a1e0::1".parse().unwrap()), None);
+        assert_eq!(
+            unmap_4via6("fd7a:115c:a1e0:b1a:1:7:a01:100".parse().unwrap()),
+            None
+        );
+
+        let route = ViaRoute::parse("fd7a:115c:a1e0:b1a:0:7:a01:100/120").unwrap();
+        assert_eq!(
+            route,
+            ViaRoute {
+                site_id: 7,
+                ipv4: Ipv4Addr::new(10, 1, 1, 0),
+                prefix_len: 24,
+            }
+        );
+        assert_eq!(route.to_string(), "fd7a:115c:a1e0:b1a:0:7:a01:100/120");
+        assert_eq!(ViaRoute::parse("fd7a:115c:a1e0:b1a:0:7:a01:100/64"), None);
+        assert_eq!(ViaRoute::parse("10.1.1.0/24"), None);
+    }
+
+    #[test]
+    fn via_routes_are_part_of_the_tailnet() {
+        let status = status_with_peer(
+            &["100.64.0.2"],
+            &["100.64.0.2/32", "fd7a:115c:a1e0:b1a:0:7:a01:100/120"],
+        );
+        let peer = status.peer.values().next().unwrap();
+        assert_eq!(peer.via_routes().len(), 1);
+
+        let site_7 = map_4via6(7, Ipv4Addr::new(10, 1, 1, 9));
+        let site_8 = map_4via6(8, Ipv4Addr::new(10, 1, 1, 9));
+        assert!(status.contains_ip(site_7.into()));
+        assert!(!status.contains_ip(site_8.into()));
+        assert!(!status.contains_ip(IpAddr::from([10, 1, 1, 9])));
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex as StdMutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex;
+
+use crate::{LocalApi, LocalApiClient, Result, Whois};
+
+/// Cache of whois lookups keyed by IP address, for proxies that identify
+/// every incoming request. The least recently used entries are evicted once
+/// the cache is full, and entries expire after a time to live so reassigned
+/// addresses are picked up. Concurrent lookups of the same address share a
+/// single request to tailscaled. Clones share the cache.
+#[derive(Clone, Debug)]
+pub struct WhoisCache<T: LocalApiClient> {
+    api: LocalApi<T>,
+    capacity: usize,
+    ttl: Duration,
+    state: Arc<StdMutex<CacheState>>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Slot of each address and when it was last used
+    slots: HashMap<IpAddr, (Arc<Slot>, u64)>,
+    /// Addresses by when they were last used, oldest first
+    order: BTreeMap<u64, IpAddr>,
+    clock: u64,
+}
+
+impl CacheState {
+    fn remove(&mut self, ip: &IpAddr) {
+        if let Some((_, used)) = self.slots.remove(ip) {
+            self.order.remove(&used);
+        }
+    }
+}
+
+type Slot = Mutex<Option<(Arc<Whois>, Instant)>>;
+
+impl<T: LocalApiClient> WhoisCache<T> {
+    /// Create a cache holding up to `capacity` addresses for `ttl` each.
+    pub fn new(api: LocalApi<T>, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            api,
+            capacity: capacity.max(1),
+            ttl,
+            state: Arc::default(),
+        }
+    }
+
+    /// Look up an address, using the cached answer when it is fresh. Failed
+    /// lookups are not cached.
+    pub async fn whois(&self, ip: IpAddr) -> Result<Arc<Whois>> {
+        let slot = self.slot(ip);
+        let mut entry = slot.lock().await;
+        if let Some((whois, fetched)) = &*entry {
+            if fetched.elapsed() < self.ttl {
+                return Ok(whois.clone());
+            }
+        }
+
+        let whois = Arc::new(self.api.whois(SocketAddr::new(ip, 0)).await?);
+        *entry = Some((whois.clone(), Instant::now()));
+
+        Ok(whois)
+    }
+
+    /// Drop the cached answer for an address.
+    pub fn invalidate(&self, ip: IpAddr) {
+        self.state.lock().unwrap().remove(&ip);
+    }
+
+    /// Drop all cached answers.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.slots.clear();
+        state.order.clear();
+    }
+
+    /// Get the slot for an address, creating it and evicting the least
+    /// recently used slot if needed.
+    fn slot(&self, ip: IpAddr) -> Arc<Slot> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.clock += 1;
+        let clock = state.clock;
+        if let Some((slot, used)) = state.slots.get_mut(&ip) {
+            state.order.remove(used);
+            state.order.insert(clock, ip);
+            *used = clock;
+            return slot.clone();
+        }
+
+        if state.slots.len() >= self.capacity {
+            if let Some((_, oldest)) = state.order.pop_first() {
+                state.slots.remove(&oldest);
+            }
+        }
+
+        let slot = Arc::new(Mutex::new(None));
+        state.slots.insert(ip, (slot.clone(), clock));
+        state.order.insert(clock, ip);
+        slot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+    use http::{Request, Response};
+    use hyper::Body;
+
+    use super::*;
+
+    /// Answers every whois and counts the lookups of each address
+    #[derive(Clone, Default)]
+    struct CountingWhois {
+        lookups: Arc<StdMutex<HashMap<String, usize>>>,
+        total: Arc<AtomicUsize>,
+    }
+
+    impl CountingWhois {
+        fn lookups(&self, addr: &str) -> usize {
+            self.lookups.lock().unwrap().get(addr).copied().unwrap_or(0)
+        }
+    }
+
+    #[async_trait]
+    impl LocalApiClient for CountingWhois {
+        async fn request(&self, request: Request<Body>) -> Result<Response<Body>> {
+            let addr = request
+                .uri()
+                .query()
+                .and_then(|query| query.strip_prefix("addr="))
+                .unwrap()
+                .to_string();
+            *self
+                .lookups
+                .lock()
+                .unwrap()
+                .entry(addr.clone())
+                .or_default() += 1;
+            self.total.fetch_add(1, Ordering::SeqCst);
+            // Give concurrent lookups a chance to pile up
+            tokio::time::sleep(Duration::from_millis(10)).await;
+
+            let whois = serde_json::json!({
+                "Node": {
+                    "ID": 1,
+                    "StableID": "n1",
+                    "Name": addr,
+                    "User": 1,
+                    "Key": "nodekey:1",
+                    "KeyExpiry": "2030-01-01T00:00:00Z",
+                    "Machine": "mkey:1",
+                    "DiscoKey": "discokey:1",
+                    "Addresses": [],
+                    "AllowedIPs": [],
+                    "Hostinfo": {},
+                    "Created": "2024-01-01T00:00:00Z",
+                    "ComputedName": null,
+                    "ComputedNameWithHost": null,
+                },
+                "UserProfile": {"ID": 1, "LoginName": "user", "DisplayName": "User"},
+            });
+            Ok(Response::new(Body::from(serde_json::to_vec(&whois)?)))
+        }
+    }
+
+    fn counting_cache(
+        capacity: usize,
+        ttl: Duration,
+    ) -> (WhoisCache<CountingWhois>, CountingWhois) {
+        let client = CountingWhois::default();
+        let cache = WhoisCache::new(LocalApi::new_with_client(client.clone()), capacity, ttl);
+        (cache, client)
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([100, 64, 0, last])
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_addresses_are_evicted() {
+        let (cache, client) = counting_cache(2, Duration::from_secs(60));
+
+        cache.whois(ip(1)).await.unwrap();
+        cache.whois(ip(2)).await.unwrap();
+        // Using the first address makes the second the oldest
+        cache.whois(ip(1)).await.unwrap();
+        cache.whois(ip(3)).await.unwrap();
+        cache.whois(ip(1)).await.unwrap();
+        cache.whois(ip(2)).await.unwrap();
+
+        assert_eq!(client.lookups("100.64.0.1:0"), 1);
+        assert_eq!(client.lookups("100.64.0.2:0"), 2);
+        assert_eq!(client.lookups("100.64.0.3:0"), 1);
+        let state = cache.state.lock().unwrap();
+        assert_eq!(state.slots.len(), 2);
+        assert_eq!(state.order.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn expired_answers_are_fetched_again() {
+        let (cache, client) = counting_cache(4, Duration::from_secs(60));
+        let whois = cache.whois(ip(1)).await.unwrap();
+        assert_eq!(whois.node.name, "100.64.0.1:0");
+        cache.whois(ip(1)).await.unwrap();
+        assert_eq!(client.total.load(Ordering::SeqCst), 1);
+
+        let (cache, client) = counting_cache(4, Duration::ZERO);
+        cache.whois(ip(1)).await.unwrap();
+        cache.whois(ip(1)).await.unwrap();
+        assert_eq!(client.total.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn invalidated_answers_are_fetched_again() {
+        let (cache, client) = counting_cache(4, Duration::from_secs(60));
+        cache.whois(ip(1)).await.unwrap();
+        cache.whois(ip(2)).await.unwrap();
+        cache.invalidate(ip(1));
+        cache.whois(ip(1)).await.unwrap();
+        cache.clear();
+        cache.whois(ip(2)).await.unwrap();
+
+        assert_eq!(client.lookups("100.64.0.1:0"), 2);
+        assert_eq!(client.lookups("100.64.0.2:0"), 2);
+    }
+
+    #[tokio::test]
+    async fn concurrent_lookups_share_a_request() {
+        let (cache, client) = counting_cache(4, Duration::from_secs(60));
+        let (first, second, other) =
+            tokio::join!(cache.whois(ip(1)), cache.whois(ip(1)), cache.whois(ip(2)));
+
+        assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+        assert_eq!(other.unwrap().node.name, "100.64.0.2:0");
+        assert_eq!(client.lookups("100.64.0.1:0"), 1);
+        assert_eq!(client.lookups("100.64.0.2:0"), 1);
+    }
+}
@@ -0,0 +1,283 @@
+//! Optional TRACE level dumps of the raw requests and responses exchanged with
+//! tailscaled. Enabled with the `wire-dump` feature; without it these helpers
+//! pass messages through untouched.
+
+use http::{Request, Response};
+use hyper::Body;
+
+use crate::Result;
+
+#[cfg(not(feature = "wire-dump"))]
+pub(crate) async fn dump_request(request: Request<Body>) -> Result<Request<Body>> {
+    Ok(request)
+}
+
+#[cfg(not(feature = "wire-dump"))]
+pub(crate) async fn dump_response(response: Response<Body>) -> Result<Response<Body>> {
+    Ok(response)
+}
+
+#[cfg(feature = "wire-dump")]
+pub(crate) async fn dump_request(request: Request<Body>) -> Result<Request<Body>> {
+    if !tracing::enabled!(tracing::Level::TRACE) {
+        return Ok(request);
+    }
+
+    let (parts, body) = request.into_parts();
+    let (body, dumped) = buffer_body(&parts.headers, body).await?;
+    tracing::trace!(
+        target: "tailscale_localapi::wire",
+        "> {} {} {:?}\n{}{}",
+        parts.method,
+        parts.uri,
+        parts.version,
+        dump_headers(&parts.headers),
+        dumped,
+    );
+
+    Ok(Request::from_parts(parts, body))
+}
+
+#[cfg(feature = "wire-dump")]
+pub(crate) async fn dump_response(response: Response<Body>) -> Result<Response<Body>> {
+    if !tracing::enabled!(tracing::Level::TRACE) {
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let (body, dumped) = buffer_body(&parts.headers, body).await?;
+    tracing::trace!(
+        target: "tailscale_localapi::wire",
+        "< {:?} {}\n{}{}",
+        parts.version,
+        parts.status,
+        dump_headers(&parts.headers),
+        dumped,
+    );
+
+    Ok(Response::from_parts(parts, body))
+}
+
+/// Largest body buffered to be logged
+#[cfg(feature = "wire-dump")]
+const MAX_DUMPED_BODY: u64 = 64 * 1024;
+
+/// Buffer a body so it can be logged. Only small JSON and text bodies are
+/// buffered; file transfers, packet captures, and other large or binary
+/// bodies are logged by their length and passed through untouched, as are
+/// streaming bodies without a known length.
+#[cfg(feature = "wire-dump")]
+async fn buffer_body(headers: &http::HeaderMap, body: Body) -> Result<(Body, String)> {
+    use hyper::body::HttpBody;
+
+    let Some(length) = body.size_hint().exact() else {
+        return Ok((body, "<streaming body>".to_string()));
+    };
+    if length > MAX_DUMPED_BODY || !is_text(headers) {
+        return Ok((body, format!("<{length} byte body>")));
+    }
+
+    let bytes = hyper::body::to_bytes(body).await?;
+    let dumped = match std::str::from_utf8(&bytes) {
+        Ok(text) => redact_body(text),
+        Err(_) => format!("<{} bytes of binary data>", bytes.len()),
+    };
+
+    Ok((Body::from(bytes), dumped))
+}
+
+/// Whether a message is marked as JSON or text
+#[cfg(feature = "wire-dump")]
+fn is_text(headers: &http::HeaderMap) -> bool {
+    let Some(content_type) = headers.get(http::header::CONTENT_TYPE) else {
+        return false;
+    };
+    let media_type = content_type
+        .to_str()
+        .unwrap_or_default()
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    media_type == "application/json"
+        || media_type.ends_with("+json")
+        || media_type.starts_with("text/")
+}
+
+/// Headers that carry credentials. Values marked sensitive, such as the header
+/// of an [`crate::AuthScheme::Custom`], are redacted as well.
+#[cfg(feature = "wire-dump")]
+const SECRET_HEADERS: [http::HeaderName; 4] = [
+    http::header::AUTHORIZATION,
+    http::header::PROXY_AUTHORIZATION,
+    http::header::COOKIE,
+    http::header::SET_COOKIE,
+];
+
+#[cfg(feature = "wire-dump")]
+fn dump_headers(headers: &http::HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            if value.is_sensitive() || SECRET_HEADERS.contains(name) {
+                format!("{name}: <redacted>\n")
+            } else {
+                format!("{name}: {}\n", String::from_utf8_lossy(value.as_bytes()))
+            }
+        })
+        .collect()
+}
+
+/// Remove PEM private keys, node and tailnet lock private keys (`privkey:` and
+/// `nlpriv:`), auth keys, and the values of JSON fields holding tokens, such
+/// as the `IDToken` from [`crate::LocalApi::id_token`], from a body before
+/// logging. Public keys such as `nodekey:` are kept.
+#[cfg(feature = "wire-dump")]
+fn redact_body(text: &str) -> String {
+    const PEM_BEGIN: &str = "-----BEGIN ";
+    const PEM_END: &str = "-----END ";
+    const SECRET_PREFIXES: [&str; 3] = ["privkey:", "nlpriv:", "tskey-"];
+    const SECRET_FIELDS: [&str; 1] = ["\"IDToken\""];
+
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        if rest.starts_with(PEM_BEGIN) {
+            let header_end = rest.find('\n').unwrap_or(rest.len());
+            if rest[..header_end].contains("PRIVATE KEY") {
+                let block_end = rest
+                    .find(PEM_END)
+                    .and_then(|end| rest[end..].find('\n').map(|line| end + line + 1))
+                    .unwrap_or(rest.len());
+                redacted.push_str("<redacted private key>\n");
+                rest = &rest[block_end..];
+                continue;
+            }
+        }
+
+        if let Some(field) = SECRET_FIELDS.iter().find(|f| rest.starts_with(**f)) {
+            if let Some(value_start) = string_value_start(&rest[field.len()..]) {
+                let value_start = field.len() + value_start;
+                let value_end = rest[value_start..]
+                    .find('"')
+                    .map_or(rest.len(), |end| value_start + end);
+                redacted.push_str(&rest[..value_start]);
+                redacted.push_str("<redacted>");
+                rest = &rest[value_end..];
+                continue;
+            }
+        }
+
+        if let Some(prefix) = SECRET_PREFIXES.iter().find(|p| rest.starts_with(**p)) {
+            let secret_end = rest
+                .find(|c: char| c == '"' || c.is_whitespace())
+                .unwrap_or(rest.len());
+            redacted.push_str(prefix);
+            redacted.push_str("<redacted>");
+            rest = &rest[secret_end..];
+            continue;
+        }
+
+        let next = rest.chars().next().unwrap();
+        redacted.push(next);
+        rest = &rest[next.len_utf8()..];
+    }
+
+    redacted
+}
+
+/// Offset of the contents of a JSON string value following a field name, as in
+/// `: "value"`, or `None` if the value is not a string
+#[cfg(feature = "wire-dump")]
+fn string_value_start(after_name: &str) -> Option<usize> {
+    let value = after_name.trim_start().strip_prefix(':')?.trim_start();
+    let value = value.strip_prefix('"')?;
+    Some(after_name.len() - value.len())
+}
+
+#[cfg(all(test, feature = "wire-dump"))]
+mod tests {
+    use super::*;
+
+    fn with_type(content_type: &str) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::CONTENT_TYPE, content_type.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn only_small_text_bodies_are_buffered() {
+        let json = with_type("application/json; charset=utf-8");
+        let (body, dumped) = buffer_body(&json, Body::from(r#"{"a":1}"#)).await.unwrap();
+        assert_eq!(dumped, r#"{"a":1}"#);
+        assert_eq!(
+            &hyper::body::to_bytes(body).await.unwrap()[..],
+            br#"{"a":1}"#
+        );
+
+        let large = vec![b'a'; MAX_DUMPED_BODY as usize + 1];
+        let (_, dumped) = buffer_body(&json, Body::from(large)).await.unwrap();
+        assert_eq!(dumped, format!("<{} byte body>", MAX_DUMPED_BODY + 1));
+
+        let octets = with_type("application/octet-stream");
+        let (_, dumped) = buffer_body(&octets, Body::from("file")).await.unwrap();
+        assert_eq!(dumped, "<4 byte body>");
+
+        let (_, dumped) = buffer_body(&http::HeaderMap::new(), Body::from("file"))
+            .await
+            .unwrap();
+        assert_eq!(dumped, "<4 byte body>");
+
+        let (_, streaming) = Body::channel();
+        let (_, dumped) = buffer_body(&json, streaming).await.unwrap();
+        assert_eq!(dumped, "<streaming body>");
+    }
+
+    #[test]
+    fn credential_headers_are_redacted() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::AUTHORIZATION, "Basic abc".parse().unwrap());
+        headers.insert(
+            http::header::PROXY_AUTHORIZATION,
+            "Basic def".parse().unwrap(),
+        );
+        headers.insert(http::header::COOKIE, "session=ghi".parse().unwrap());
+        let mut custom: http::HeaderValue = "jkl".parse().unwrap();
+        custom.set_sensitive(true);
+        headers.insert("x-api-key", custom);
+        headers.insert(http::header::ACCEPT, "application/json".parse().unwrap());
+
+        assert_eq!(
+            dump_headers(&headers),
+            "authorization: <redacted>\n\
+             proxy-authorization: <redacted>\n\
+             cookie: <redacted>\n\
+             x-api-key: <redacted>\n\
+             accept: application/json\n"
+        );
+    }
+
+    #[test]
+    fn id_tokens_are_redacted() {
+        assert_eq!(
+            redact_body(r#"{"IDToken":"eyJhbGciOi.eyJzdWIi.c2lnbmF0dXJl"}"#),
+            r#"{"IDToken":"<redacted>"}"#
+        );
+        assert_eq!(
+            redact_body("{\n  \"IDToken\": \"eyJhbGciOi\",\n  \"Other\": \"kept\"\n}"),
+            "{\n  \"IDToken\": \"<redacted>\",\n  \"Other\": \"kept\"\n}"
+        );
+        assert_eq!(redact_body(r#"{"IDToken":null}"#), r#"{"IDToken":null}"#);
+    }
+
+    #[test]
+    fn secrets_are_redacted_but_public_keys_kept() {
+        let body = r#"{"PrivateNodeKey":"privkey:abc","LockKey":"nlpriv:def","AuthKey":"tskey-auth-123","NodeKey":"nodekey:456"}"#;
+        assert_eq!(
+            redact_body(body),
+            r#"{"PrivateNodeKey":"privkey:<redacted>","LockKey":"nlpriv:<redacted>","AuthKey":"tskey-<redacted>","NodeKey":"nodekey:456"}"#
+        );
+    }
+}